@@ -0,0 +1,144 @@
+use log::warn;
+use std::borrow::Cow;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "embedded")]
+use rust_embed::RustEmbed;
+
+// Where the installation files are read from. Paths are relative to the installation
+// folder and always separated by `/`.
+pub trait AssetSource: std::fmt::Debug + Send + Sync {
+    fn list(&self) -> Vec<String>;
+    fn get_file(&self, path: &str) -> Option<Cow<'static, [u8]>>;
+}
+
+// Picks the asset source: a folder or archive given on the command line, the files embedded
+// in the binary, a `data.bin` next to the installer, or else an `installation/` folder.
+pub fn open_asset_source(path: Option<String>) -> anyhow::Result<Box<dyn AssetSource>> {
+    match path {
+        Some(p) if Path::new(&p).is_dir() => Ok(Box::new(DirectorySource::new(p))),
+        Some(p) => Ok(Box::new(ArchiveSource::open(p)?)),
+        None => default_source(),
+    }
+}
+
+#[cfg(feature = "embedded")]
+fn default_source() -> anyhow::Result<Box<dyn AssetSource>> {
+    Ok(Box::new(EmbeddedSource))
+}
+
+#[cfg(not(feature = "embedded"))]
+fn default_source() -> anyhow::Result<Box<dyn AssetSource>> {
+    // Next to the executable, as it may be started from another working directory
+    let exe = std::env::current_exe()?;
+    let dir = exe.parent().unwrap_or(Path::new("."));
+    let data = dir.join("data.bin");
+    if data.exists() {
+        Ok(Box::new(ArchiveSource::open(data)?))
+    } else {
+        Ok(Box::new(DirectorySource::new(dir.join("installation"))))
+    }
+}
+
+#[cfg(feature = "embedded")]
+#[derive(RustEmbed, Debug)]
+#[folder = "installation/"]
+pub struct EmbeddedSource;
+
+#[cfg(feature = "embedded")]
+impl AssetSource for EmbeddedSource {
+    fn list(&self) -> Vec<String> {
+        EmbeddedSource::iter().map(|f| f.to_string()).collect()
+    }
+    fn get_file(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        EmbeddedSource::get(path)
+    }
+}
+
+// A zip of the installation folder, such as `data.bin`. The archive is opened once and
+// files are read out of it as they are asked for.
+#[derive(Debug)]
+pub struct ArchiveSource {
+    archive: std::sync::Mutex<zip::ZipArchive<std::fs::File>>,
+    // Relative path to the name of the entry in the archive
+    index: std::collections::HashMap<String, String>,
+}
+impl ArchiveSource {
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let archive = zip::ZipArchive::new(file)?;
+
+        // Archives zipped from the `installation` folder itself keep it as a prefix
+        let index = archive
+            .file_names()
+            .filter(|f| !f.ends_with('/'))
+            .map(|f| {
+                let relative = f.replace("\\", "/");
+                let relative = relative
+                    .strip_prefix("installation/")
+                    .unwrap_or(&relative)
+                    .to_owned();
+                (relative, f.to_owned())
+            })
+            .collect();
+
+        Ok(ArchiveSource {
+            archive: std::sync::Mutex::new(archive),
+            index,
+        })
+    }
+}
+impl AssetSource for ArchiveSource {
+    fn list(&self) -> Vec<String> {
+        self.index.keys().cloned().collect()
+    }
+    fn get_file(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        let name = self.index.get(path)?;
+        let mut archive = self.archive.lock().unwrap();
+        let mut file = archive.by_name(name).ok()?;
+        let mut data = Vec::with_capacity(file.size() as usize);
+        match file.read_to_end(&mut data) {
+            Ok(_) => Some(data.into()),
+            Err(e) => {
+                warn!("Couldn't read {} from archive: {}", path, e.to_string());
+                None
+            }
+        }
+    }
+}
+
+// A plain folder on disk, laid out like `installation/`
+#[derive(Debug)]
+pub struct DirectorySource {
+    root: PathBuf,
+}
+impl DirectorySource {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        DirectorySource { root: root.into() }
+    }
+}
+impl AssetSource for DirectorySource {
+    fn list(&self) -> Vec<String> {
+        walkdir::WalkDir::new(&self.root)
+            .into_iter()
+            .filter_map(|f| match f {
+                Ok(f) => Some(f),
+                Err(e) => {
+                    warn!("Error reading asset: {}", e.to_string());
+                    None
+                }
+            })
+            .filter(|f| f.file_type().is_file())
+            .filter_map(|f| {
+                f.path()
+                    .strip_prefix(&self.root)
+                    .ok()
+                    .map(|p| p.to_string_lossy().replace("\\", "/"))
+            })
+            .collect()
+    }
+    fn get_file(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        std::fs::read(self.root.join(path)).ok().map(|f| f.into())
+    }
+}
@@ -0,0 +1,402 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+// Each install's cleanup is moved into its own set in the `Plugins_bak` folder next to
+// Plugins, either a folder or a zip named after the time it was made. Every set carries an
+// index of where its files came from.
+pub const BACKUP_INDEX: &str = "backup.json";
+const SET_ID_FORMAT: &str = "%Y-%m-%d %H-%M-%S";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BackupIndex {
+    pub id: String,
+    pub created: String,
+    pub plugins: String,
+    pub files: Vec<BackupFile>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BackupFile {
+    // Full path the file was moved from
+    pub original: String,
+    // Path of the file inside the backup set
+    pub backup: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupSummary {
+    pub id: String,
+    pub created: String,
+    pub archive: bool,
+    pub files: usize,
+    pub size: u64,
+}
+
+// The `Plugins_bak` folder next to `plugins`
+pub fn backup_root(plugins: &Path) -> PathBuf {
+    let name = plugins.file_name().unwrap_or_default().to_string_lossy();
+    plugins.with_file_name(format!("{}_bak", name))
+}
+
+// A set being filled. Files are moved into a folder named after the set; sets stored as
+// zips are packed from that folder when finished. Sets are serializable so an unfinished
+// install's journal can pick them up again.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BackupSet {
+    plugins: PathBuf,
+    id: String,
+    created: String,
+    as_zip: bool,
+    dir: PathBuf,
+}
+
+impl BackupSet {
+    pub fn create(plugins: &Path, as_zip: bool) -> anyhow::Result<Self> {
+        let root = backup_root(plugins);
+        std::fs::create_dir_all(&root)?;
+
+        let now = chrono::Local::now();
+        let mut id = now.format(SET_ID_FORMAT).to_string();
+        let mut n = 1;
+        while root.join(&id).exists() || root.join(format!("{}.zip", id)).exists() {
+            n += 1;
+            id = format!("{} ({})", now.format(SET_ID_FORMAT), n);
+        }
+
+        let dir = root.join(&id);
+        std::fs::create_dir(&dir)?;
+        Ok(BackupSet {
+            plugins: plugins.to_owned(),
+            id,
+            created: now.to_rfc3339(),
+            as_zip,
+            dir,
+        })
+    }
+
+    pub fn plugins(&self) -> &Path {
+        &self.plugins
+    }
+
+    // Where `file` goes in the set, keeping its path below Plugins
+    fn backup_path(&self, file: &Path) -> PathBuf {
+        match file.strip_prefix(&self.plugins) {
+            Ok(relative) => self.dir.join(relative),
+            Err(_) => self.dir.join(file.file_name().unwrap_or_default()),
+        }
+    }
+
+    // Moves a file out of Plugins into the set. A file already moved, by an install that
+    // was interrupted, is left where it is.
+    pub fn add(&mut self, file: &Path) -> anyhow::Result<()> {
+        let backup = self.backup_path(file);
+        if !file.exists() && backup.exists() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(backup.parent().unwrap())?;
+        move_file(file, &backup)?;
+        Ok(())
+    }
+
+    // Moves `file` back to Plugins, if it was moved into the set
+    pub fn undo(&self, file: &Path) -> anyhow::Result<()> {
+        let backup = self.backup_path(file);
+        if backup.exists() {
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            move_file(&backup, file)?;
+        }
+        Ok(())
+    }
+
+    // Removes the set's folder once every file has been moved back out of it
+    pub fn discard(self) -> anyhow::Result<()> {
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+
+    // The files in the set's folder, by their path inside it
+    fn files(&self) -> Vec<BackupFile> {
+        let mut files: Vec<BackupFile> = walkdir::WalkDir::new(&self.dir)
+            .into_iter()
+            .filter_map(|f| f.ok())
+            .filter(|f| f.file_type().is_file())
+            .filter_map(|f| {
+                let relative = f.path().strip_prefix(&self.dir).ok()?.to_owned();
+                if relative == Path::new(BACKUP_INDEX) {
+                    return None;
+                }
+                Some(BackupFile {
+                    original: self.plugins.join(&relative).to_string_lossy().to_string(),
+                    backup: relative.to_string_lossy().replace("\\", "/"),
+                    size: f.metadata().map(|m| m.len()).unwrap_or(0),
+                })
+            })
+            .collect();
+        files.sort_by(|a, b| a.backup.cmp(&b.backup));
+        files
+    }
+
+    // Writes the index, packing the set into a zip if asked, or removes the set again if
+    // nothing was moved into it. Finishing a set twice does nothing more.
+    pub fn finish(self) -> anyhow::Result<BackupIndex> {
+        let index = BackupIndex {
+            id: self.id.clone(),
+            created: self.created.clone(),
+            plugins: self.plugins.to_string_lossy().to_string(),
+            files: self.files(),
+        };
+        if !self.dir.exists() {
+            return Ok(index);
+        }
+        if index.files.is_empty() {
+            std::fs::remove_dir_all(&self.dir)?;
+            return Ok(index);
+        }
+
+        let index_json = serde_json::to_string_pretty(&index)?;
+        if self.as_zip {
+            let path = self.dir.with_file_name(format!("{}.zip", self.id));
+            let mut zip = zip::ZipWriter::new(std::fs::File::create(&path)?);
+            for file in &index.files {
+                zip.start_file(file.backup.as_str(), zip::write::FileOptions::default())?;
+                zip.write_all(&std::fs::read(self.dir.join(&file.backup))?)?;
+            }
+            zip.start_file(BACKUP_INDEX, zip::write::FileOptions::default())?;
+            zip.write_all(index_json.as_bytes())?;
+            zip.finish()?;
+            std::fs::remove_dir_all(&self.dir)?;
+        } else {
+            std::fs::write(self.dir.join(BACKUP_INDEX), index_json)?;
+        }
+        Ok(index)
+    }
+}
+
+// Renames where it can, and copies when the backup is on another drive
+pub fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::rename(from, to) {
+        Ok(_) => Ok(()),
+        Err(_) => {
+            std::fs::copy(from, to)?;
+            std::fs::remove_file(from)
+        }
+    }
+}
+
+pub fn read_index(set: &Path) -> anyhow::Result<BackupIndex> {
+    let data = if set.is_dir() {
+        std::fs::read(set.join(BACKUP_INDEX))?
+    } else {
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(set)?)?;
+        let mut file = zip.by_name(BACKUP_INDEX)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        data
+    };
+    Ok(serde_json::from_slice(&data)?)
+}
+
+fn set_size(set: &Path) -> u64 {
+    walkdir::WalkDir::new(set)
+        .into_iter()
+        .filter_map(|f| f.ok())
+        .filter_map(|f| f.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+// Every backup set next to `plugins`, newest first. Loose files from older installers,
+// which made no sets, are left out.
+pub fn list_backups(plugins: &Path) -> Vec<(PathBuf, BackupSummary)> {
+    let entries = match std::fs::read_dir(backup_root(plugins)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut sets: Vec<(PathBuf, BackupSummary)> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter_map(|path| {
+            let index = read_index(&path).ok()?;
+            let summary = BackupSummary {
+                id: index.id,
+                created: index.created,
+                archive: path.is_file(),
+                files: index.files.len(),
+                size: set_size(&path),
+            };
+            Some((path, summary))
+        })
+        .collect();
+    sets.sort_by(|a, b| b.1.created.cmp(&a.1.created));
+    sets
+}
+
+// Deletes the oldest sets past the newest `keep`, with 0 keeping every set
+pub fn apply_retention(plugins: &Path, keep: usize) {
+    if keep == 0 {
+        return;
+    }
+    for (path, summary) in list_backups(plugins).into_iter().skip(keep) {
+        let removed = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        match removed {
+            Ok(_) => info!("Removed old backup set: {}", summary.id),
+            Err(e) => warn!("Couldn't remove old backup set {}: {}", summary.id, e),
+        }
+    }
+}
+
+// Whether `id` is a set's id, the time it was made with a count after it if that time was
+// taken, e.g. `2024-01-31 18-05-09 (2)`. Ids come from requests, so this also keeps them
+// from naming anything outside `Plugins_bak`.
+fn is_set_id(id: &str) -> bool {
+    let mut components = Path::new(id).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return false;
+    }
+    let time = match id.split_once(" (") {
+        Some((time, n)) => match n.strip_suffix(')') {
+            Some(n) if n.parse::<usize>().is_ok() => time,
+            _ => return false,
+        },
+        None => id,
+    };
+    chrono::NaiveDateTime::parse_from_str(time, SET_ID_FORMAT).is_ok()
+}
+
+// The set with this id next to `plugins`, as a folder or zip
+pub fn find_set(plugins: &Path, id: &str) -> Option<PathBuf> {
+    if !is_set_id(id) {
+        return None;
+    }
+    let root = backup_root(plugins);
+    [root.join(id), root.join(format!("{}.zip", id))]
+        .iter()
+        .find(|p| p.exists())
+        .cloned()
+}
+
+fn read_file(set: &Path, backup: &str) -> anyhow::Result<Vec<u8>> {
+    if set.is_dir() {
+        Ok(std::fs::read(set.join(backup))?)
+    } else {
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(set)?)?;
+        let mut file = zip.by_name(backup)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RestoreReport {
+    pub restored: Vec<String>,
+    pub conflicts: Vec<RestoreConflict>,
+    // Asked for but not in the set
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreConflict {
+    pub file: String,
+    pub original: String,
+    pub reason: String,
+}
+
+// Copies files out of a set back to where they were, leaving the set as it is. `files` are
+// paths inside the set, or `None` for all of them. Files whose original path is taken, or
+// whose name is in `nam_files` (lowercase), are reported as conflicts and only restored
+// with `force`.
+pub fn restore(
+    set: &Path,
+    files: Option<&[String]>,
+    nam_files: &std::collections::HashSet<String>,
+    force: bool,
+) -> anyhow::Result<RestoreReport> {
+    let index = read_index(set)?;
+    let mut report = RestoreReport::default();
+
+    let chosen: Vec<&BackupFile> = match files {
+        Some(files) => {
+            for f in files
+                .iter()
+                .filter(|f| !index.files.iter().any(|b| &b.backup == *f))
+            {
+                report.missing.push(f.to_owned());
+            }
+            index
+                .files
+                .iter()
+                .filter(|b| files.contains(&b.backup))
+                .collect()
+        }
+        None => index.files.iter().collect(),
+    };
+
+    for file in chosen {
+        let original = Path::new(&file.original);
+        let name = original
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        let conflict = if original.exists() {
+            Some("a file already exists at its original path")
+        } else if nam_files.contains(&name) {
+            Some("a file of the same name is installed by the NAM")
+        } else {
+            None
+        };
+        if let Some(reason) = conflict {
+            report.conflicts.push(RestoreConflict {
+                file: file.backup.clone(),
+                original: file.original.clone(),
+                reason: reason.to_string(),
+            });
+            if !force {
+                continue;
+            }
+        }
+
+        let data = read_file(set, &file.backup)?;
+        if let Some(parent) = original.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(original, data)?;
+        info!("Restored {} from backup set {}", file.original, index.id);
+        report.restored.push(file.backup.clone());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_ids_are_times() {
+        assert!(is_set_id("2024-01-31 18-05-09"));
+        assert!(is_set_id("2024-01-31 18-05-09 (2)"));
+        assert!(!is_set_id("2024-01-31 18-05-09 (x)"));
+        assert!(!is_set_id("Unfinished install"));
+        assert!(!is_set_id("../2024-01-31 18-05-09"));
+        assert!(!is_set_id("2024-01-31 18-05-09/.."));
+        assert!(!is_set_id(".."));
+        assert!(find_set(Path::new("/tmp/Plugins"), "../../etc").is_none());
+    }
+}
@@ -0,0 +1,253 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::dbpf;
+use crate::paths;
+
+// Files the user chose to keep when cleanup offered to move them, so they aren't offered again.
+// Written to the Plugins folder, with paths relative to it.
+pub const KEPT_FILES: &str = "NAM Installer Kept Files.json";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CleanupMatch {
+    // Relative to the Plugins folder, separated by `/`
+    pub path: String,
+    // The Plugins folder the file is in
+    pub location: String,
+    // Whether that is the Plugins folder of the game install rather than the user's
+    pub game: bool,
+    pub size: u64,
+    // The line of `cleanup.txt` that matched
+    pub rule: String,
+    // The selected option whose cleanup rule matched, or none for the global list
+    pub option: Option<String>,
+    pub matched_by: MatchKind,
+    // Matched by name alone, so it may be the user's own file of the same name
+    pub review: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchKind {
+    Sha1,
+    Tgi,
+    Name,
+}
+
+// A line of `cleanup.txt`: a file name, optionally followed by what the outdated file is known
+// to look like, e.g. `old.dat | sha1:<hash> | tgi:<type>-<group>-<instance>`. A file with the
+// name only matches if its hash is one of those given, or it has every TGI given.
+#[derive(Debug, Clone, Default)]
+struct CleanupRule {
+    line: String,
+    option: Option<String>,
+    sha1: Vec<String>,
+    tgis: Vec<dbpf::Tgi>,
+}
+impl CleanupRule {
+    fn parse(line: &str) -> (String, Self) {
+        let mut parts = line.split('|').map(|p| p.trim());
+        let name = parts.next().unwrap_or_default().to_owned();
+        let mut rule = CleanupRule {
+            line: line.to_owned(),
+            ..Default::default()
+        };
+        for part in parts {
+            if let Some(hash) = part.strip_prefix("sha1:") {
+                rule.sha1.push(hash.trim().to_lowercase());
+            } else if let Some(tgi) = part.strip_prefix("tgi:").and_then(dbpf::parse_tgi) {
+                rule.tgis.push(tgi);
+            } else {
+                warn!("Ignoring unknown cleanup signature: {} in {}", part, line);
+            }
+        }
+        (name, rule)
+    }
+
+    // How the file is known to be the outdated one, if it is
+    fn check(&self, path: &Path) -> Option<MatchKind> {
+        if self.sha1.is_empty() && self.tgis.is_empty() {
+            return Some(MatchKind::Name);
+        }
+        if !self.sha1.is_empty() {
+            match file_sha1(path) {
+                Ok(hash) if self.sha1.contains(&hash) => return Some(MatchKind::Sha1),
+                Ok(_) => (),
+                Err(e) => warn!("Couldn't hash {}: {}", path.display(), e.to_string()),
+            }
+        }
+        if !self.tgis.is_empty() {
+            if let Ok(tgis) = dbpf::read_tgis(path) {
+                if self.tgis.iter().all(|t| tgis.contains(t)) {
+                    return Some(MatchKind::Tgi);
+                }
+            }
+        }
+        None
+    }
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(data))
+}
+
+pub fn file_sha1(path: &Path) -> std::io::Result<String> {
+    Ok(sha1_hex(&std::fs::read(path)?))
+}
+
+// The rules of `cleanup.txt` and of the selected options, by lowercase file name. An option's
+// rules are tried before the global ones for the same name.
+#[derive(Debug, Clone, Default)]
+pub struct CleanupRules {
+    rules: HashMap<String, Vec<CleanupRule>>,
+}
+impl CleanupRules {
+    pub fn parse(text: &str) -> Self {
+        let mut rules = CleanupRules::default();
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let (name, rule) = CleanupRule::parse(line);
+            rules
+                .rules
+                .entry(name.to_lowercase())
+                .or_default()
+                .push(rule);
+        }
+        rules
+    }
+
+    pub fn add_option(&mut self, option: &str, lines: &[String]) {
+        for line in lines.iter().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let (name, mut rule) = CleanupRule::parse(line);
+            rule.option = Some(option.to_owned());
+            self.rules
+                .entry(name.to_lowercase())
+                .or_default()
+                .insert(0, rule);
+        }
+    }
+
+    fn matches(&self, path: &Path) -> Option<(&CleanupRule, MatchKind)> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        self.rules
+            .get(&name)?
+            .iter()
+            .find_map(|rule| rule.check(path).map(|kind| (rule, kind)))
+    }
+}
+
+// Every file below `plugins` that a rule matches
+pub fn find_matches(plugins: &Path, rules: &CleanupRules) -> Vec<CleanupMatch> {
+    walkdir::WalkDir::new(plugins)
+        .into_iter()
+        .filter_map(|f| match f {
+            Ok(f) => Some(f),
+            Err(e) => {
+                warn!("{}", e.to_string());
+                None
+            }
+        })
+        .filter(|f| f.file_type().is_file())
+        .filter_map(|f| {
+            let (rule, kind) = rules.matches(f.path())?;
+            Some(CleanupMatch {
+                path: f
+                    .path()
+                    .strip_prefix(plugins)
+                    .unwrap_or_else(|_| f.path())
+                    .to_string_lossy()
+                    .replace("\\", "/"),
+                location: plugins.to_string_lossy().to_string(),
+                game: false,
+                size: f.metadata().map(|m| m.len()).unwrap_or(0),
+                rule: rule.line.clone(),
+                option: rule.option.clone(),
+                matched_by: kind,
+                review: kind == MatchKind::Name,
+            })
+        })
+        .collect()
+}
+
+// The matches still to be offered, and the ones the user kept before
+pub fn preview(plugins: &Path, rules: &CleanupRules) -> (Vec<CleanupMatch>, Vec<CleanupMatch>) {
+    let kept = read_kept(plugins)
+        .iter()
+        .map(|k| k.to_lowercase())
+        .collect::<HashSet<String>>();
+    find_matches(plugins, rules)
+        .into_iter()
+        .partition(|m| !kept.contains(&m.path.to_lowercase()))
+}
+
+pub fn read_kept(plugins: &Path) -> Vec<String> {
+    std::fs::read(plugins.join(KEPT_FILES))
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+pub fn add_kept(plugins: &Path, files: &[String]) -> anyhow::Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let mut kept = read_kept(plugins);
+    for file in files {
+        if !kept.iter().any(|k| paths::eq_ignore_case(k, file)) {
+            kept.push(file.to_owned());
+        }
+    }
+    std::fs::write(
+        plugins.join(KEPT_FILES),
+        serde_json::to_string_pretty(&kept)?,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_match_file_names_without_case() {
+        let dir = std::env::temp_dir().join(format!("nam-cleanup-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("plugins")).unwrap();
+        let file = dir.join("plugins/NETWORKADDONMOD.dat");
+        std::fs::write(&file, b"").unwrap();
+
+        let rules = CleanupRules::parse("NetworkAddonMod.dat\nall_stations_fix.dat\n");
+        let (rule, kind) = rules.matches(&file).unwrap();
+        assert_eq!(rule.line, "NetworkAddonMod.dat");
+        assert_eq!(kind, MatchKind::Name);
+        assert!(rules.matches(&dir.join("plugins/Other.dat")).is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rules_match_known_hashes_and_tgis() {
+        let dir = std::env::temp_dir().join(format!("nam-cleanup-sigs-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("old.dat");
+        std::fs::write(&old, b"old").unwrap();
+        let lot = dir.join("lot.SC4Lot");
+        std::fs::write(&lot, dbpf::build(&[[0x6534284A, 0x4A693A2B, 0x0C], [1, 2, 3]])).unwrap();
+
+        let rules = CleanupRules::parse(&format!(
+            "old.dat | sha1:{}\nlot.SC4Lot | tgi:6534284A-4A693A2B-0000000C | tgi:1-2-3\n",
+            sha1_hex(b"old").to_uppercase()
+        ));
+        assert_eq!(rules.matches(&old).unwrap().1, MatchKind::Sha1);
+        assert_eq!(rules.matches(&lot).unwrap().1, MatchKind::Tgi);
+
+        // Files of the same name that look different are the user's own
+        std::fs::write(&old, b"the user's own").unwrap();
+        assert!(rules.matches(&old).is_none());
+        std::fs::write(&lot, dbpf::build(&[[1, 2, 3]])).unwrap();
+        assert!(rules.matches(&lot).is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
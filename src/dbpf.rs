@@ -0,0 +1,137 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+// SimCity 4's `.dat`, `.SC4Lot` and other plugin files are DBPF archives. Each entry in the
+// index is named by a type, group and instance id (TGI), which is enough to tell versions of a
+// plugin apart without hashing it.
+
+pub type Tgi = [u32; 3];
+
+fn read_u32(buf: &[u8], at: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&buf[at..at + 4]);
+    u32::from_le_bytes(b)
+}
+
+// The TGIs of every entry in the file's index
+pub fn read_tgis(path: &Path) -> anyhow::Result<Vec<Tgi>> {
+    let mut file = std::fs::File::open(path)?;
+    let mut header = [0; 96];
+    file.read_exact(&mut header)?;
+    if &header[0..4] != b"DBPF" {
+        return Err(anyhow::anyhow!("not a DBPF file"));
+    }
+
+    let count = read_u32(&header, 36) as usize;
+    let offset = read_u32(&header, 40) as u64;
+    let size = read_u32(&header, 44) as usize;
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    // 20 bytes per entry in SC4 files, more in later index versions
+    let entry_size = size / count;
+    if entry_size < 12 {
+        return Err(anyhow::anyhow!("unsupported DBPF index"));
+    }
+
+    // A damaged header can point the index past the end of the file
+    if offset + size as u64 > file.metadata()?.len() {
+        return Err(anyhow::anyhow!("DBPF index past the end of the file"));
+    }
+
+    let mut index = vec![0; entry_size * count];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut index)?;
+    Ok(index
+        .chunks(entry_size)
+        .map(|e| [read_u32(e, 0), read_u32(e, 4), read_u32(e, 8)])
+        .collect())
+}
+
+// A TGI written as three hex ids, e.g. `6534284A-4A693A2B-0000000C`
+pub fn parse_tgi(text: &str) -> Option<Tgi> {
+    let parts = text
+        .split(&['-', ','][..])
+        .map(|p| u32::from_str_radix(p.trim().trim_start_matches("0x"), 16).ok())
+        .collect::<Option<Vec<u32>>>()?;
+    match parts.as_slice() {
+        [t, g, i] => Some([*t, *g, *i]),
+        _ => None,
+    }
+}
+
+// A DBPF file with an index of `tgis` and no entry data, for tests
+#[cfg(test)]
+pub fn build(tgis: &[Tgi]) -> Vec<u8> {
+    let mut data = vec![0; 96];
+    data[0..4].copy_from_slice(b"DBPF");
+    data[36..40].copy_from_slice(&(tgis.len() as u32).to_le_bytes());
+    data[40..44].copy_from_slice(&96u32.to_le_bytes());
+    data[44..48].copy_from_slice(&(tgis.len() as u32 * 20).to_le_bytes());
+    for tgi in tgis {
+        for id in tgi.iter().chain(&[0, 0]) {
+            data.extend_from_slice(&id.to_le_bytes());
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("nam-dbpf-{}-{}", name, std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn parse_tgi_reads_hex_ids() {
+        assert_eq!(
+            parse_tgi("6534284A-4A693A2B-0000000C"),
+            Some([0x6534284A, 0x4A693A2B, 0x0C])
+        );
+        assert_eq!(
+            parse_tgi("0x6534284a, 0x4a693a2b, 0xc"),
+            Some([0x6534284A, 0x4A693A2B, 0x0C])
+        );
+        assert_eq!(parse_tgi("6534284A-4A693A2B"), None);
+        assert_eq!(parse_tgi("6534284A-4A693A2B-nothex"), None);
+    }
+
+    #[test]
+    fn read_tgis_lists_the_index() {
+        let tgis = [[0x6534284A, 0x4A693A2B, 0x0C], [1, 2, 3]];
+        let path = temp_file("index", &build(&tgis));
+        assert_eq!(read_tgis(&path).unwrap(), tgis);
+        std::fs::remove_file(path).unwrap();
+
+        let path = temp_file("empty", &build(&[]));
+        assert!(read_tgis(&path).unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_tgis_rejects_damaged_files() {
+        let path = temp_file("not-dbpf", b"not a DBPF file, but long enough to hold a header");
+        assert!(read_tgis(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+
+        // An index far past the end of the file isn't allocated
+        let mut data = build(&[[1, 2, 3]]);
+        data[36..40].copy_from_slice(&100_000_000u32.to_le_bytes());
+        data[44..48].copy_from_slice(&2_000_000_000u32.to_le_bytes());
+        let path = temp_file("past-eof", &data);
+        assert!(read_tgis(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+
+        // Or just past it
+        let mut data = build(&[[1, 2, 3]]);
+        data[44..48].copy_from_slice(&40u32.to_le_bytes());
+        data[36..40].copy_from_slice(&2u32.to_le_bytes());
+        let path = temp_file("truncated", &data);
+        assert!(read_tgis(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+
+use crate::manifest::InstallManifest;
+
+// Below this share of a release's files, Plugins isn't taken to have that release installed
+pub const MIN_CONFIDENCE: f64 = 0.5;
+
+// The names of the files making up a NAM release
+#[derive(Debug, Clone)]
+pub struct Fingerprint {
+    pub version: String,
+    pub files: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectionSource {
+    Manifest,
+    Fingerprint,
+    None,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InstalledNam {
+    pub installed: bool,
+    pub version: Option<String>,
+    pub installer_version: Option<String>,
+    // Option ids, as sent in `files_to_install`
+    pub options: Vec<String>,
+    // Share of the detected install's files found in Plugins, from 0 to 1
+    pub confidence: f64,
+    pub source: DetectionSource,
+}
+
+// The lowercase name of every file below `plugins`
+fn index_plugins(plugins: &Path) -> HashSet<String> {
+    walkdir::WalkDir::new(plugins)
+        .into_iter()
+        .filter_map(|f| f.ok())
+        .filter(|f| f.file_type().is_file())
+        .map(|f| f.file_name().to_string_lossy().to_lowercase())
+        .collect()
+}
+
+fn fingerprint_score(fingerprint: &Fingerprint, index: &HashSet<String>) -> f64 {
+    if fingerprint.files.is_empty() {
+        return 0.0;
+    }
+    let found = fingerprint
+        .files
+        .iter()
+        .filter(|name| index.contains(&name.to_lowercase()))
+        .count();
+    found as f64 / fingerprint.files.len() as f64
+}
+
+// Works out which NAM is installed in `plugins`. The manifest of the last install is trusted
+// if there is one, otherwise the folder is compared against this release's fingerprint.
+// `option_files` are the file names each option of this release installs, for guessing which
+// options a fingerprinted install has.
+pub fn detect(
+    plugins: &Path,
+    fingerprint: &Fingerprint,
+    option_files: &[(String, Vec<String>)],
+) -> InstalledNam {
+    if let Some(manifest) = InstallManifest::read(plugins) {
+        let present = manifest
+            .files
+            .iter()
+            .filter(|f| plugins.join(&f.destination).exists())
+            .count();
+        let confidence = if manifest.files.is_empty() {
+            1.0
+        } else {
+            present as f64 / manifest.files.len() as f64
+        };
+        return InstalledNam {
+            installed: true,
+            version: Some(manifest.nam_version),
+            installer_version: Some(manifest.installer_version),
+            options: manifest.selected,
+            confidence,
+            source: DetectionSource::Manifest,
+        };
+    }
+
+    let index = index_plugins(plugins);
+    match fingerprint_score(fingerprint, &index) {
+        confidence if confidence >= MIN_CONFIDENCE => InstalledNam {
+            installed: true,
+            version: Some(fingerprint.version.clone()),
+            installer_version: None,
+            options: option_files
+                .iter()
+                .filter(|(_, files)| !files.is_empty())
+                .filter(|(_, files)| files.iter().all(|f| index.contains(&f.to_lowercase())))
+                .map(|(id, _)| id.to_owned())
+                .collect(),
+            confidence,
+            source: DetectionSource::Fingerprint,
+        },
+        _ => InstalledNam {
+            installed: false,
+            version: None,
+            installer_version: None,
+            options: Vec::new(),
+            confidence: 0.0,
+            source: DetectionSource::None,
+        },
+    }
+}
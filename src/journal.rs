@@ -0,0 +1,680 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::backup::{self, BackupSet};
+use crate::cleanup::{self, CleanupMatch};
+use crate::manifest::{InstallManifest, ManifestFile};
+use crate::paths;
+
+// An install writes every step it is going to take to a journal before touching Plugins, and
+// appends a line as each step completes. If the installer is killed halfway, the journal is
+// found the next time it starts, and the install can be resumed or rolled back from it. It's
+// kept in `Plugins_bak` along with the files the install replaced, out of sight of the game.
+pub const JOURNAL_FOLDER: &str = "Unfinished install";
+const JOURNAL_FILE: &str = "journal.json";
+const PROGRESS_FILE: &str = "progress.log";
+const REPLACED_FOLDER: &str = "replaced";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupReason {
+    Cleanup(CleanupMatch),
+    // Installed last time, but not by this upgrade
+    Removed(String),
+    // Changed by the user since the last install
+    Modified(String),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    // Move a file into one of the journal's backup sets
+    Backup {
+        set: usize,
+        file: PathBuf,
+        reason: BackupReason,
+    },
+    // Write a file of the package to Plugins, or move it there from the staging folder in a
+    // staged install. Whatever `existed` at the destination is set aside until the install is
+    // done, so a rollback can put it back, and the `new_dirs` folders above it that the
+    // install creates are removed again.
+    Write {
+        option: String,
+        source: String,
+        destination: String,
+        existed: bool,
+        #[serde(default)]
+        new_dirs: usize,
+    },
+    // Write a file of the package to the staging folder, for a staged install
+    Stage {
+        source: String,
+        destination: String,
+    },
+    // Every file of an option has been written
+    OptionDone {
+        folder: String,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Progress {
+    // A `Write` step is about to put the file with this hash in Plugins
+    Started { step: usize, sha1: String },
+    Done { step: usize, sha1: Option<String> },
+    // The install is being finished, and can no longer be rolled back
+    Commit,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalSummary {
+    pub location: String,
+    pub created: String,
+    pub nam_version: String,
+    pub steps: usize,
+    pub done: usize,
+    pub committing: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Journal {
+    pub created: String,
+    pub nam_version: String,
+    pub plugins: PathBuf,
+    pub backup_retention: usize,
+    // Build every file in the staging folder before Plugins is touched, and undo everything
+    // if any step fails
+    #[serde(default)]
+    pub staged: bool,
+    pub sets: Vec<BackupSet>,
+    // Cleanup the user declined, recorded for each Plugins folder once the install is done
+    #[serde(default)]
+    pub kept: Vec<(PathBuf, Vec<String>)>,
+    // Written once the install is done, with the files of the steps added to the ones the
+    // install left as they were
+    pub manifest: InstallManifest,
+    pub steps: Vec<Step>,
+    // Completed steps, with the hash of the file written
+    #[serde(skip)]
+    done: HashMap<usize, Option<String>>,
+    // `Write` steps begun, with the hash of the file they write
+    #[serde(skip)]
+    started: HashMap<usize, String>,
+    #[serde(skip)]
+    committing: bool,
+    #[serde(skip)]
+    progress: Option<std::fs::File>,
+}
+
+// The journal's folder for the install into `plugins`
+pub fn journal_dir(plugins: &Path) -> PathBuf {
+    backup::backup_root(plugins).join(JOURNAL_FOLDER)
+}
+
+// The folder next to `plugins` a staged install builds its files in
+pub fn staging_dir(plugins: &Path) -> PathBuf {
+    let name = plugins.file_name().unwrap_or_default().to_string_lossy();
+    plugins.with_file_name(format!("{}_staging", name))
+}
+
+pub fn exists(plugins: &Path) -> bool {
+    journal_dir(plugins).join(JOURNAL_FILE).exists()
+}
+
+// Removes the journal without undoing anything, for one that can't be read
+pub fn discard(plugins: &Path) -> anyhow::Result<()> {
+    std::fs::remove_dir_all(journal_dir(plugins))?;
+    Ok(())
+}
+
+impl Journal {
+    pub fn new(
+        plugins: &Path,
+        nam_version: &str,
+        backup_retention: usize,
+        manifest: InstallManifest,
+    ) -> Self {
+        Journal {
+            created: chrono::Local::now().to_rfc3339(),
+            nam_version: nam_version.to_owned(),
+            plugins: plugins.to_owned(),
+            backup_retention,
+            staged: false,
+            sets: Vec::new(),
+            kept: Vec::new(),
+            manifest,
+            steps: Vec::new(),
+            done: HashMap::new(),
+            started: HashMap::new(),
+            committing: false,
+            progress: None,
+        }
+    }
+
+    pub fn add_set(&mut self, set: BackupSet) -> usize {
+        self.sets.push(set);
+        self.sets.len() - 1
+    }
+
+    // A `Write` step, noting the folders it will create
+    pub fn write_step(&self, option: &str, source: &str, destination: &str, existed: bool) -> Step {
+        let path = paths::resolve(&self.plugins.join(destination));
+        let new_dirs = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| *dir != self.plugins.as_path() && !dir.exists())
+            .count();
+        Step::Write {
+            option: option.to_owned(),
+            source: source.to_owned(),
+            destination: destination.to_owned(),
+            existed,
+            new_dirs,
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        journal_dir(&self.plugins)
+    }
+
+    fn open_progress(&mut self) -> anyhow::Result<()> {
+        self.progress = Some(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.dir().join(PROGRESS_FILE))?,
+        );
+        Ok(())
+    }
+
+    // Writes the journal out before any step is taken. Only one install into a Plugins
+    // folder can be unfinished at a time.
+    pub fn begin(mut self) -> anyhow::Result<Self> {
+        if exists(&self.plugins) {
+            return Err(anyhow::anyhow!(
+                "an unfinished install is already in the way"
+            ));
+        }
+        let dir = self.dir();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| anyhow::anyhow!("couldn't create {:?}: {}", dir, e))?;
+        // A journal cut off while being written would stand in the way of every later install
+        paths::write_atomic(
+            &dir.join(JOURNAL_FILE),
+            serde_json::to_string_pretty(&self)?.as_bytes(),
+        )?;
+        self.open_progress()?;
+        Ok(self)
+    }
+
+    // The unfinished install into `plugins`, if there is one
+    pub fn open(plugins: &Path) -> Option<anyhow::Result<Self>> {
+        if !exists(plugins) {
+            return None;
+        }
+        Some(Self::read(plugins))
+    }
+
+    fn read(plugins: &Path) -> anyhow::Result<Self> {
+        let dir = journal_dir(plugins);
+        let mut journal: Journal = serde_json::from_slice(&std::fs::read(dir.join(JOURNAL_FILE))?)?;
+        if let Ok(file) = std::fs::File::open(dir.join(PROGRESS_FILE)) {
+            // The last line may have been cut off by whatever stopped the install
+            for line in std::io::BufReader::new(file).lines().map_while(Result::ok) {
+                match serde_json::from_str(&line) {
+                    Ok(Progress::Started { step, sha1 }) => {
+                        journal.started.insert(step, sha1);
+                    }
+                    Ok(Progress::Done { step, sha1 }) => {
+                        journal.done.insert(step, sha1);
+                    }
+                    Ok(Progress::Commit) => journal.committing = true,
+                    Err(_) => warn!("Ignoring journal line: {}", line),
+                }
+            }
+        }
+        journal.open_progress()?;
+        Ok(journal)
+    }
+
+    pub fn summary(&self) -> JournalSummary {
+        JournalSummary {
+            location: self.plugins.to_string_lossy().to_string(),
+            created: self.created.clone(),
+            nam_version: self.nam_version.clone(),
+            steps: self.steps.len(),
+            done: self.done.len(),
+            committing: self.committing,
+        }
+    }
+
+    pub fn is_done(&self, step: usize) -> bool {
+        self.done.contains_key(&step)
+    }
+
+    fn record(&mut self, progress: &Progress) -> anyhow::Result<()> {
+        let file = self
+            .progress
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("journal not open"))?;
+        file.write_all(format!("{}\n", serde_json::to_string(progress)?).as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    pub fn mark_done(&mut self, step: usize, sha1: Option<String>) -> anyhow::Result<()> {
+        self.record(&Progress::Done {
+            step,
+            sha1: sha1.clone(),
+        })?;
+        self.done.insert(step, sha1);
+        Ok(())
+    }
+
+    pub fn backup(&mut self, step: usize) -> anyhow::Result<()> {
+        match &self.steps[step] {
+            Step::Backup { set, file, .. } => self.sets[*set].add(file),
+            _ => Err(anyhow::anyhow!("step {} isn't a backup", step)),
+        }
+    }
+
+    // Where the file at `destination` before the install is kept until it's done
+    fn replaced_path(&self, destination: &str) -> PathBuf {
+        self.dir().join(REPLACED_FOLDER).join(destination)
+    }
+
+    // Writes the file of a `Write` step, returning its hash
+    pub fn write(&mut self, step: usize, data: &[u8]) -> anyhow::Result<String> {
+        let (destination, existed) = match &self.steps[step] {
+            Step::Write {
+                destination,
+                existed,
+                ..
+            } => (destination.clone(), *existed),
+            _ => return Err(anyhow::anyhow!("step {} isn't a write", step)),
+        };
+        let sha1 = cleanup::sha1_hex(data);
+        self.start(step, &sha1)?;
+        let (path, replaced) = self.set_aside(&destination, existed)?;
+        if let Err(e) = paths::write_atomic(&path, data) {
+            if replaced.exists() {
+                backup::move_file(&replaced, &path)?;
+            }
+            return Err(e.into());
+        }
+        Ok(sha1)
+    }
+
+    // Notes the hash of the file a `Write` step puts in Plugins before it does, so a rollback
+    // can tell that file from one the install never wrote
+    fn start(&mut self, step: usize, sha1: &str) -> anyhow::Result<()> {
+        self.record(&Progress::Started {
+            step,
+            sha1: sha1.to_owned(),
+        })?;
+        self.started.insert(step, sha1.to_owned());
+        Ok(())
+    }
+
+    // Moves what was at the destination of a `Write` step out of the way, returning the
+    // destination and where the old file is kept
+    fn set_aside(&self, destination: &str, existed: bool) -> anyhow::Result<(PathBuf, PathBuf)> {
+        // Reuse an existing file or folder even if it is cased differently
+        let path = paths::resolve(&self.plugins.join(destination));
+        let replaced = self.replaced_path(destination);
+        if existed && path.exists() && !replaced.exists() {
+            std::fs::create_dir_all(replaced.parent().unwrap())?;
+            backup::move_file(&path, &replaced)?;
+        }
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        Ok((path, replaced))
+    }
+
+    // Writes the file of a `Stage` step to the staging folder, returning its hash
+    pub fn stage(&mut self, step: usize, data: &[u8]) -> anyhow::Result<String> {
+        let destination = match &self.steps[step] {
+            Step::Stage { destination, .. } => destination.clone(),
+            _ => return Err(anyhow::anyhow!("step {} isn't a stage", step)),
+        };
+        let path = staging_dir(&self.plugins).join(&destination);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        paths::write_atomic(&path, data)?;
+        Ok(cleanup::sha1_hex(data))
+    }
+
+    // Moves the staged file of a `Write` step into Plugins, returning its hash
+    pub fn place(&mut self, step: usize) -> anyhow::Result<String> {
+        let (destination, existed) = match &self.steps[step] {
+            Step::Write {
+                destination,
+                existed,
+                ..
+            } => (destination.clone(), *existed),
+            _ => return Err(anyhow::anyhow!("step {} isn't a write", step)),
+        };
+        let sha1 = self
+            .steps
+            .iter()
+            .enumerate()
+            .find(|(_, s)| matches!(s, Step::Stage { destination: d, .. } if *d == destination))
+            .and_then(|(i, _)| self.done.get(&i).cloned().flatten())
+            .ok_or_else(|| anyhow::anyhow!("{} wasn't staged", destination))?;
+        self.start(step, &sha1)?;
+
+        let staged = staging_dir(&self.plugins).join(&destination);
+        let (path, replaced) = self.set_aside(&destination, existed)?;
+        if let Err(e) = backup::move_file(&staged, &path) {
+            if replaced.exists() {
+                backup::move_file(&replaced, &path)?;
+            }
+            return Err(e.into());
+        }
+        Ok(sha1)
+    }
+
+    // Finishes the backup sets, writes the manifest and removes the journal
+    pub fn commit(mut self) -> anyhow::Result<InstallManifest> {
+        self.record(&Progress::Commit)?;
+        for (plugins, kept) in &self.kept {
+            cleanup::add_kept(plugins, kept)
+                .unwrap_or_else(|e| warn!("Unable to record kept files: {}", e.to_string()));
+        }
+        for set in self.sets.iter().cloned() {
+            let plugins = set.plugins().to_owned();
+            match set.finish() {
+                Ok(index) => info!(
+                    "Backed up {} files from {} to set: {}",
+                    index.files.len(),
+                    index.plugins,
+                    index.id
+                ),
+                Err(e) => warn!("Unable to finish backup set: {}", e.to_string()),
+            }
+            backup::apply_retention(&plugins, self.backup_retention);
+        }
+
+        let mut manifest = self.manifest.clone();
+        for (i, step) in self.steps.iter().enumerate() {
+            if let Step::Write {
+                option,
+                source,
+                destination,
+                ..
+            } = step
+            {
+                if let Some(sha1) = self.done.get(&i) {
+                    manifest.files.push(ManifestFile {
+                        option: option.clone(),
+                        source: source.clone(),
+                        destination: destination.clone(),
+                        sha1: sha1.clone(),
+                    });
+                }
+            }
+        }
+        manifest.write(&self.plugins)?;
+        self.progress = None;
+        self.remove_staging()?;
+        std::fs::remove_dir_all(self.dir())?;
+        Ok(manifest)
+    }
+
+    fn remove_staging(&self) -> anyhow::Result<()> {
+        let staging = staging_dir(&self.plugins);
+        if self.staged && staging.exists() {
+            std::fs::remove_dir_all(staging)?;
+        }
+        Ok(())
+    }
+
+    // Whether the file at `path` was put there by `Write` step `step`. A step cut off before
+    // it was marked done only counts if the file is the one it was writing.
+    fn wrote(&self, step: usize, path: &Path) -> bool {
+        if !path.is_file() {
+            return false;
+        }
+        if self.done.contains_key(&step) {
+            return true;
+        }
+        match self.started.get(&step) {
+            Some(sha1) => cleanup::file_sha1(path).is_ok_and(|s| s == *sha1),
+            None => false,
+        }
+    }
+
+    // Undoes every step taken, newest first, and removes the journal. The step after the
+    // last one completed may have been cut off halfway, so it is undone too.
+    pub fn rollback(mut self) -> anyhow::Result<()> {
+        if self.committing {
+            return Err(anyhow::anyhow!(
+                "the install was already being finished, resume it instead"
+            ));
+        }
+        let reached = match self.done.keys().max() {
+            Some(last) => (last + 2).min(self.steps.len()),
+            None => self.steps.len().min(1),
+        };
+        for (i, step) in self.steps[..reached].iter().enumerate().rev() {
+            match step {
+                Step::Backup { set, file, .. } => {
+                    self.sets[*set].undo(file)?;
+                }
+                Step::Write {
+                    destination,
+                    new_dirs,
+                    ..
+                } => {
+                    let path = paths::resolve(&self.plugins.join(destination));
+                    let replaced = self.replaced_path(destination);
+                    let temp = paths::temp_sibling(&path);
+                    if temp.exists() {
+                        std::fs::remove_file(&temp)?;
+                    }
+                    if replaced.exists() {
+                        backup::move_file(&replaced, &path)?;
+                    } else if self.wrote(i, &path) {
+                        std::fs::remove_file(&path)?;
+                    }
+                    // Only succeeds once the folder is empty again
+                    for dir in path.ancestors().skip(1).take(*new_dirs) {
+                        let _ = std::fs::remove_dir(dir);
+                    }
+                }
+                Step::Stage { .. } | Step::OptionDone { .. } => (),
+            }
+        }
+        for set in self.sets.drain(..) {
+            set.discard()?;
+        }
+        self.remove_staging()?;
+        self.progress = None;
+        std::fs::remove_dir_all(self.dir())?;
+        info!("Rolled back the unfinished install into {:?}", self.plugins);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    // A fresh `Plugins` folder in the system temp folder, holding `files` with their contents
+    fn temp_plugins(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nam-journal-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let plugins = dir.join("Plugins");
+        for (file, contents) in files {
+            let path = plugins.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        plugins
+    }
+
+    // Every file below `dir`, with its contents
+    fn snapshot(dir: &Path) -> BTreeMap<String, String> {
+        walkdir::WalkDir::new(dir)
+            .into_iter()
+            .filter_map(|f| f.ok())
+            .filter(|f| f.file_type().is_file())
+            .map(|f| {
+                let relative = f.path().strip_prefix(dir).unwrap();
+                (
+                    relative.to_string_lossy().replace('\\', "/"),
+                    std::fs::read_to_string(f.path()).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn journal(plugins: &Path) -> Journal {
+        let mut journal = Journal::new(plugins, "40", 5, InstallManifest::default());
+        journal.add_set(BackupSet::create(plugins, false).unwrap());
+        journal
+    }
+
+    fn removed(plugins: &Path, file: &str) -> Step {
+        Step::Backup {
+            set: 0,
+            file: plugins.join(file),
+            reason: BackupReason::Removed(file.to_owned()),
+        }
+    }
+
+    fn assert_rolled_back(plugins: &Path, before: &BTreeMap<String, String>) {
+        assert_eq!(&snapshot(plugins), before);
+        assert!(!exists(plugins));
+        assert!(!staging_dir(plugins).exists());
+        assert!(snapshot(&backup::backup_root(plugins)).is_empty());
+        std::fs::remove_dir_all(plugins.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn rollback_after_a_failed_backup_keeps_the_users_file() {
+        let plugins = temp_plugins("failed-backup", &[("mine.dat", "changed by the user")]);
+        let before = snapshot(&plugins);
+
+        let mut journal = journal(&plugins);
+        journal.steps = vec![
+            Step::Backup {
+                set: 0,
+                file: plugins.join("mine.dat"),
+                reason: BackupReason::Modified("mine.dat".to_owned()),
+            },
+            // Planned as not existing, as the backup was to move it away first
+            journal.write_step("Core", "Core/mine.dat", "mine.dat", false),
+            journal.write_step("Core", "Core/new.dat", "new.dat", false),
+        ];
+        let mut journal = journal.begin().unwrap();
+
+        // The backup failed, so the write over the user's file was skipped
+        let sha1 = journal.write(2, b"new").unwrap();
+        journal.mark_done(2, Some(sha1)).unwrap();
+
+        journal.rollback().unwrap();
+        assert_rolled_back(&plugins, &before);
+    }
+
+    #[test]
+    fn staged_rollback_leaves_plugins_untouched() {
+        let plugins = temp_plugins("staged", &[("old.dat", "old"), ("keep.dat", "keep")]);
+        let before = snapshot(&plugins);
+
+        let mut journal = journal(&plugins);
+        journal.staged = true;
+        let stage = |destination: &str| Step::Stage {
+            source: format!("Core/{}", destination),
+            destination: destination.to_owned(),
+        };
+        journal.steps = vec![
+            stage("zzz_NAM/a.dat"),
+            stage("keep.dat"),
+            removed(&plugins, "old.dat"),
+            journal.write_step("Core", "Core/zzz_NAM/a.dat", "zzz_NAM/a.dat", false),
+            journal.write_step("Core", "Core/keep.dat", "keep.dat", true),
+            Step::OptionDone {
+                folder: "Core".to_owned(),
+            },
+        ];
+        let mut journal = journal.begin().unwrap();
+
+        for (step, data) in [(0, "a"), (1, "new keep")] {
+            let sha1 = journal.stage(step, data.as_bytes()).unwrap();
+            journal.mark_done(step, Some(sha1)).unwrap();
+        }
+        assert!(snapshot(&plugins) == before);
+        journal.backup(2).unwrap();
+        journal.mark_done(2, None).unwrap();
+        let sha1 = journal.place(3).unwrap();
+        journal.mark_done(3, Some(sha1)).unwrap();
+
+        // The staged file has gone missing, which fails the install
+        std::fs::remove_file(staging_dir(&plugins).join("keep.dat")).unwrap();
+        assert!(journal.place(4).is_err());
+        assert_eq!(std::fs::read_to_string(plugins.join("keep.dat")).unwrap(), "keep");
+
+        journal.rollback().unwrap();
+        assert_rolled_back(&plugins, &before);
+    }
+
+    #[test]
+    fn cancelling_midway_restores_plugins() {
+        let plugins = temp_plugins("cancel", &[("old.dat", "old"), ("keep.dat", "keep")]);
+        let before = snapshot(&plugins);
+
+        let mut journal = journal(&plugins);
+        journal.steps = vec![
+            removed(&plugins, "old.dat"),
+            journal.write_step("Core", "Core/zzz_NAM/a.dat", "zzz_NAM/a.dat", false),
+            journal.write_step("Core", "Core/keep.dat", "keep.dat", true),
+            journal.write_step("Core", "Core/b.dat", "b.dat", false),
+        ];
+        let mut journal = journal.begin().unwrap();
+
+        journal.backup(0).unwrap();
+        journal.mark_done(0, None).unwrap();
+        for (step, data) in [(1, "a"), (2, "new keep")] {
+            let sha1 = journal.write(step, data.as_bytes()).unwrap();
+            journal.mark_done(step, Some(sha1)).unwrap();
+        }
+        assert!(!plugins.join("old.dat").exists());
+        assert_eq!(std::fs::read_to_string(plugins.join("keep.dat")).unwrap(), "new keep");
+
+        // Cancelled before the last step
+        journal.rollback().unwrap();
+        assert_rolled_back(&plugins, &before);
+    }
+
+    #[test]
+    fn an_interrupted_install_is_resumed_or_rolled_back() {
+        let plugins = temp_plugins("interrupted", &[("keep.dat", "keep")]);
+        let before = snapshot(&plugins);
+
+        let mut journal = journal(&plugins);
+        journal.steps = vec![
+            journal.write_step("Core", "Core/a.dat", "a.dat", false),
+            journal.write_step("Core", "Core/b.dat", "b.dat", false),
+            journal.write_step("Core", "Core/c.dat", "c.dat", false),
+        ];
+        let mut journal = journal.begin().unwrap();
+        let sha1 = journal.write(0, b"a").unwrap();
+        journal.mark_done(0, Some(sha1)).unwrap();
+        // Killed after writing the file, before it was marked done
+        journal.write(1, b"b").unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&plugins).unwrap().unwrap();
+        assert!(journal.is_done(0));
+        assert!(!journal.is_done(1));
+        assert_eq!(journal.summary().done, 1);
+
+        journal.rollback().unwrap();
+        assert!(Journal::open(&plugins).is_none());
+        assert_rolled_back(&plugins, &before);
+    }
+}
@@ -7,21 +7,22 @@ use log4rs::encode::pattern::PatternEncoder;
 use os_info;
 use percent_encoding;
 use serde::{Deserialize, Serialize};
+use std::env;
 use std::path::Path;
-use std::{env, str::FromStr};
-use walkdir::WalkDir;
+use std::sync::atomic::{AtomicBool, Ordering};
 use warp::{http::Response, Filter};
 
-#[cfg(target_pointer_width = "32")]
-use std::io::{Cursor, Read};
+mod assets;
+mod backup;
+mod cleanup;
+mod dbpf;
+mod detect;
+mod journal;
+mod manifest;
+mod paths;
+mod upgrade;
 
-#[cfg(target_pointer_width = "64")]
-use rust_embed::RustEmbed;
-
-#[cfg(target_pointer_width = "64")]
-#[derive(RustEmbed)]
-#[folder = "installation/"]
-struct InstallAsset;
+use assets::AssetSource;
 
 type Result<T> = std::result::Result<T, warp::Rejection>;
 
@@ -33,6 +34,7 @@ const FAVICON_ICO: &[u8] = include_bytes!("../static/favicon.ico");
 const CONFIG: &str = include_str!("../configuration.json");
 const FOUR_GB: &[u8] = include_bytes!("../static/4gb_patch.exe");
 const CLEANUP: &str = include_str!("../static/cleanup.txt");
+const OPTION_METADATA: &str = "option.json";
 
 fn rust_version() -> String {
     env!("CARGO_PKG_VERSION").into()
@@ -47,127 +49,206 @@ struct Configuration {
     web_server_port: u16,
     #[serde(default)]
     windows: String,
+    // Extensions of the files installed from an option folder, compared case insensitively
+    #[serde(default = "default_file_types")]
+    file_types: Vec<String>,
+    // Extensions of the files that go in the root of Plugins, unless the option places them
+    #[serde(default = "default_root_file_types")]
+    root_file_types: Vec<String>,
+    // Store each cleanup's backup set as a zip rather than a folder
+    #[serde(default)]
+    backup_as_zip: bool,
+    // How many backup sets to keep, 0 keeps them all
+    #[serde(default = "default_backup_retention")]
+    backup_retention: usize,
+}
+impl Configuration {
+    fn is_installable(&self, file: &str) -> bool {
+        has_file_type(file, &self.file_types)
+    }
+    fn is_root_file(&self, file: &str) -> bool {
+        has_file_type(file, &self.root_file_types)
+    }
 }
 
-fn calculate_folders(strs: &mut Vec<String>) -> Vec<String> {
-    let as_paths = strs.iter().map(|s| Path::new(s));
-    let mut folders: Vec<String> = as_paths
-        .map(|p| {
-            let name = p.file_name().unwrap().to_string_lossy().to_string();
-            let full_path = p.to_string_lossy().to_string();
-            full_path.replace(&name, "")
-        })
-        .collect();
-    folders.sort();
-    folders.dedup();
-    folders.append(strs);
-    folders
+fn has_file_type(file: &str, types: &[String]) -> bool {
+    match Path::new(file).extension() {
+        Some(ext) => types.iter().any(|t| {
+            t.trim_start_matches('.')
+                .eq_ignore_ascii_case(&ext.to_string_lossy())
+        }),
+        None => false,
+    }
+}
+
+fn default_file_types() -> Vec<String> {
+    ["dat", "SC4Lot", "SC4Model", "SC4Desc", "SC4Path", "dll"]
         .iter()
-        .filter(|f| f.len() > 0)
-        .map(|f| f.to_string())
-        .collect::<Vec<String>>()
+        .map(|t| t.to_string())
+        .collect()
+}
+
+fn default_root_file_types() -> Vec<String> {
+    vec!["dll".to_string()]
+}
+
+fn default_backup_retention() -> usize {
+    5
+}
+
+// Files in option folders used by the installer itself, never installed or reported as ignored
+fn is_installer_file(file: &str) -> bool {
+    file.ends_with(OPTION_METADATA)
+        || file.contains(".txt")
+        || file.contains(".jpg")
+        || file.contains(".png")
 }
 
 // State Checkers to prevent spoofed http calls from causing a mangled installation, including making sure windows sc4 is patched
 static mut CHECKED_EXE: bool = false;
+static mut CHECKED_EXE_PATH: Option<String> = None;
 static mut PATCHED_EXE: bool = false;
 
 // List of cleaned files
 static mut CLEANED_FILE_COUNT: usize = 0;
 static mut CLEANED_FILE_MAX: usize = 0;
 static mut CLEANED_FILE_LIST: Vec<String> = Vec::new();
+// What matched each moved file, and the option whose rule it was
+static mut CLEANED_FILE_MATCHES: Vec<cleanup::CleanupMatch> = Vec::new();
 // List of installed files
 static mut INSTALLED_FILE_COUNT: usize = 0;
 static mut INSTALLED_FILE_MAX: usize = 0;
 static mut INSTALLED_FILE_LIST: Vec<String> = Vec::new();
+// List of files in the selected options that aren't an installable type
+static mut IGNORED_FILE_LIST: Vec<String> = Vec::new();
+
+static mut MODIFIED_FILE_LIST: Vec<ModifiedFile> = Vec::new();
+// Files that couldn't be installed, and why
+static mut FAILED_FILE_LIST: Vec<FailedFile> = Vec::new();
+// Whether a staged install failed and Plugins was put back as it was
+static mut INSTALL_ROLLED_BACK: bool = false;
+// Set by `/install/cancel`, and checked before every step of a running install
+static CANCEL_INSTALL: AtomicBool = AtomicBool::new(false);
+static INSTALL_RUNNING: AtomicBool = AtomicBool::new(false);
+static mut INSTALL_CANCELLED: bool = false;
+// Why the install couldn't be started
+static mut INSTALL_ERROR: Option<String> = None;
 
 #[derive(Clone, Debug)]
 struct InstallAssetList {
     list: Vec<String>,
+    source: std::sync::Arc<dyn AssetSource>,
+    // Index of the asset folders, relative to `installation/`, to the files directly in them
+    folders: std::collections::HashMap<String, Vec<String>>,
 }
 impl InstallAssetList {
-    #[cfg(target_pointer_width = "64")]
-    fn get_file(&self, f: &str, _: std::sync::Arc<String>) -> std::option::Option<std::borrow::Cow<'static, [u8]>> {
-        InstallAsset::get(f)
+    fn get_file(&self, f: &str) -> std::option::Option<std::borrow::Cow<'static, [u8]>> {
+        self.source.get_file(f)
     }
-    #[cfg(target_pointer_width = "32")]
-    fn get_file(
-        &self,
-        f: &str,
-        uuid: std::sync::Arc<String>,
-    ) -> std::option::Option<std::borrow::Cow<'static, [u8]>> {
-        Some(
-            std::fs::read(&format!("C:/temp/{}/{}", uuid.to_string(), f))
-                .unwrap()
-                .into(),
-        )
+
+    // The files directly inside a folder, given relative to `installation/`
+    fn folder_files(&self, folder: &str) -> &[String] {
+        self.folders
+            .get(folder)
+            .map(|f| f.as_slice())
+            .unwrap_or(&[])
     }
 
-    fn to_vec(self) -> Vec<String> {
+    // Paths of the assets relative to `installation/`, always separated by `/`
+    fn relative_paths(&self) -> Vec<String> {
         self.list
+            .iter()
+            .filter_map(|f| f.strip_prefix("installation/").map(|f| f.to_owned()))
+            .filter(|f| !f.is_empty())
+            .collect()
     }
 
-    fn filter_images(self) -> Self {
-        InstallAssetList {
-            list: self
-                .list
-                .iter()
-                .filter(|f| f.contains(".jpg") || f.contains(".png"))
-                .map(|f| f.to_owned())
-                .collect(),
-        }
+    // Every `option.json` in the assets, keyed by the folder it describes
+    fn get_all_metadata(
+        &self,
+        paths: &[String],
+    ) -> std::collections::HashMap<String, OptionMetadata> {
+        paths
+            .iter()
+            .filter_map(|f| f.strip_suffix(&format!("/{}", OPTION_METADATA)))
+            .filter_map(|folder| {
+                self.get_metadata(folder)
+                    .map(|meta| (folder.to_owned(), meta))
+            })
+            .collect()
     }
-    fn filter_docs(self) -> Self {
-        InstallAssetList {
-            list: self
-                .list
-                .iter()
-                .filter(|f| f.contains(".txt"))
-                .map(|f| f.to_owned())
-                .collect(),
+
+    // Reads the optional `option.json` for an option folder, given relative to `installation/`
+    fn get_metadata(&self, folder: &str) -> Option<OptionMetadata> {
+        let file = self.get_file(&format!("{}/{}", folder, OPTION_METADATA))?;
+        match serde_json::from_slice::<OptionMetadata>(&file) {
+            // Packs can come from anywhere, so they mustn't write outside Plugins
+            Ok(meta) => match meta.paths().into_iter().find(|p| !paths::is_contained(p)) {
+                Some(path) => {
+                    warn!("Invalid {} in {}: `{}` leaves Plugins", OPTION_METADATA, folder, path);
+                    None
+                }
+                None => Some(meta),
+            },
+            Err(e) => {
+                warn!("Invalid {} in {}: {}", OPTION_METADATA, folder, e.to_string());
+                None
+            }
         }
     }
-}
-#[cfg(target_pointer_width = "64")]
-async fn get_install_asset_list(_: String) -> anyhow::Result<InstallAssetList> {
-    Ok(InstallAssetList {
-        list: WalkDir::new("installation/")
-            .into_iter()
-            .map(|f| f.unwrap().path().to_string_lossy().to_string())
-            .collect(),
-    })
-}
-
-#[cfg(target_pointer_width = "32")]
-async fn get_install_asset_list(uuid: String) -> anyhow::Result<InstallAssetList> {
-    std::fs::create_dir_all(&format!("C:/temp/{}", uuid))?;
 
-    let file = std::fs::File::open("data.bin")?;
+    fn filter_images(mut self) -> Self {
+        self.list
+            .retain(|f| f.contains(".jpg") || f.contains(".png"));
+        self
+    }
+    fn filter_docs(mut self) -> Self {
+        self.list.retain(|f| f.contains(".txt"));
+        self
+    }
+}
+async fn get_install_asset_list(path: Option<String>) -> anyhow::Result<InstallAssetList> {
+    let source = assets::open_asset_source(path)?;
+    info!("Reading installation files from: {:?}", source);
 
-    let mut zip = zip::ZipArchive::new(file)?;
+    let list: Vec<String> = source
+        .list()
+        .iter()
+        .map(|f| format!("installation/{}", f))
+        .collect();
 
-    for index in 0..zip.len() {
-        let mut file = zip.by_index(index)?;
-        let name = file.name();
-        if name.ends_with('/') {
-            std::fs::create_dir_all(format!("C:/temp/{}/{}", uuid, &file.name()))?;
-        } else {
-            let mut output = std::fs::File::create(&format!("C:/temp/{}/{}", uuid, &file.name()))?;
-            std::io::copy(&mut file, &mut output)?;
+    let mut folders: std::collections::HashMap<String, Vec<String>> =
+        std::collections::HashMap::new();
+    for file in &list {
+        if let Some(idx) = file.rfind('/') {
+            let folder = file[..idx]
+                .strip_prefix("installation")
+                .unwrap_or(&file[..idx])
+                .trim_start_matches('/');
+            folders
+                .entry(folder.to_owned())
+                .or_default()
+                .push(file.to_owned());
         }
     }
+
     Ok(InstallAssetList {
-        list: WalkDir::new(&format!("C:/temp/{}", uuid))
-            .into_iter()
-            .map(|f| f.unwrap().path().to_string_lossy().to_string())
-            .collect(),
+        list,
+        source: source.into(),
+        folders,
     })
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    #[cfg(target_pointer_width = "32")]
-    assert_eq!(true, std::path::Path::new("data.bin").exists());
+    // `backups` and `restore` work on backup sets from the command line, without the server
+    let args: Vec<String> = env::args().skip(1).collect();
+    if matches!(
+        args.first().map(|a| a.as_str()),
+        Some("backups") | Some("restore")
+    ) {
+        return run_backup_command(&args).await;
+    }
 
     let mut term = term::stdout().unwrap();
     term.fg(term::color::RED).unwrap();
@@ -200,36 +281,18 @@ async fn main() -> anyhow::Result<()> {
 
     config.windows = windows;
 
-    println!("\nStarting to unzip files. Do not close this window.");
-    let temp_folder_uuid = uuid::Uuid::new_v4().to_hyphenated().to_string()[0..8].to_string();
-    let asset_iter = get_install_asset_list(temp_folder_uuid.clone()).await?;
-    println!("Finished unzipping files. Do not close this window.\n");
+    println!("\nStarting to read installation files. Do not close this window.");
+    let asset_iter = get_install_asset_list(env::args().nth(1)).await?;
+    println!("Finished reading installation files. Do not close this window.\n");
 
-    let file_list: Vec<String> = calculate_folders(&mut asset_iter.clone().to_vec());
+    offer_unfinished_install(&asset_iter, &config).await?;
 
-    let uuid = uuid::Uuid::new_v4().to_hyphenated().to_string()[0..8].to_string();
-
-    for folder in file_list
-        .iter()
-        .filter(|f| match std::path::PathBuf::from_str(f) {
-            Ok(a) => a.is_dir(),
-            Err(e) => {
-                info!("{}", e.to_string());
-                false
-            }
-        })
-    {
-        std::fs::create_dir_all(format!("{}/{}", uuid, folder))?;
-    }
-
-    let folder_structure = [folder_structure(&format!("{}/", uuid))?].to_vec();
+    let asset_paths = asset_iter.relative_paths();
+    let metadata = asset_iter.get_all_metadata(&asset_paths);
+    let folder_structure = [folder_structure(&asset_paths, &metadata)?].to_vec();
 
     let arc_folder_structure = std::sync::Arc::new(folder_structure.clone());
 
-    let arc_temp_folder_uuid: std::sync::Arc<String> =
-        std::sync::Arc::new(temp_folder_uuid.clone());
-    let arc_temp_folder_uuid_2: std::sync::Arc<String> =
-        std::sync::Arc::new(temp_folder_uuid.clone());
     let arc_asset_list: std::sync::Arc<InstallAssetList> = std::sync::Arc::new(asset_iter.clone());
 
     let arc_docs_list: std::sync::Arc<InstallAssetList> =
@@ -237,11 +300,29 @@ async fn main() -> anyhow::Result<()> {
     let arc_images_list: std::sync::Arc<InstallAssetList> =
         std::sync::Arc::new(asset_iter.clone().filter_images());
 
-    std::fs::remove_dir_all(format!("{}", uuid))?;
-
     info!("{:#?}", config);
     info!("App version: {:#?}", config.rust_version);
 
+    let arc_config = std::sync::Arc::new(config.clone());
+    let arc_config_2 = arc_config.clone();
+    let arc_folder_structure_2 = arc_folder_structure.clone();
+    let arc_folder_structure_3 = arc_folder_structure.clone();
+    let arc_asset_list_2 = arc_asset_list.clone();
+    let arc_folder_structure_4 = arc_folder_structure.clone();
+    let arc_asset_list_3 = arc_asset_list.clone();
+    let arc_config_3 = arc_config.clone();
+    let arc_folder_structure_5 = arc_folder_structure.clone();
+    let arc_asset_list_4 = arc_asset_list.clone();
+    let arc_config_4 = arc_config.clone();
+    let arc_folder_structure_6 = arc_folder_structure.clone();
+    let arc_asset_list_5 = arc_asset_list.clone();
+    let arc_config_5 = arc_config.clone();
+    let arc_folder_structure_7 = arc_folder_structure.clone();
+    let arc_asset_list_6 = arc_asset_list.clone();
+    let arc_config_6 = arc_config.clone();
+    let arc_asset_list_7 = arc_asset_list.clone();
+    let arc_config_7 = arc_config.clone();
+
     let index_html = {
         let handlebars = handlebars::Handlebars::new();
         handlebars.render_template(&INDEX_TEMPLATE, &config)?
@@ -249,7 +330,30 @@ async fn main() -> anyhow::Result<()> {
 
     let get_structure = warp::get()
         .and(warp::path("structure"))
-        .map(move || warp::reply::json(&folder_structure))
+        .and(warp::query::<PluginsQuery>())
+        .map(move |query: PluginsQuery| {
+            (
+                query,
+                arc_folder_structure_6.clone(),
+                arc_asset_list_5.clone(),
+                arc_config_5.clone(),
+            )
+        })
+        .and_then(load_structure)
+        .boxed();
+
+    let get_previous_selection = warp::get()
+        .and(warp::path!("previous_selection"))
+        .and(warp::query::<PluginsQuery>())
+        .map(move |query: PluginsQuery| {
+            (
+                query,
+                arc_folder_structure_7.clone(),
+                arc_asset_list_6.clone(),
+                arc_config_6.clone(),
+            )
+        })
+        .and_then(load_previous_selection)
         .boxed();
 
     let get_static = warp::get()
@@ -259,13 +363,7 @@ async fn main() -> anyhow::Result<()> {
 
     let get_docs = warp::get()
         .and(warp::path!("docs" / String))
-        .map(move |path: String| {
-            (
-                path.clone(),
-                arc_docs_list.clone(),
-                arc_temp_folder_uuid.clone(),
-            )
-        })
+        .map(move |path: String| (path.clone(), arc_docs_list.clone()))
         .and_then(load_local_file)
         .boxed();
 
@@ -291,13 +389,7 @@ async fn main() -> anyhow::Result<()> {
 
     let get_images = warp::get()
         .and(warp::path!("images" / String))
-        .map(move |path: String| {
-            (
-                path.clone(),
-                arc_images_list.clone(),
-                arc_temp_folder_uuid_2.clone(),
-            )
-        })
+        .map(move |path: String| (path.clone(), arc_images_list.clone()))
         .and_then(load_local_image)
         .boxed();
 
@@ -321,9 +413,110 @@ async fn main() -> anyhow::Result<()> {
                 json.clone(),
                 arc_folder_structure.clone(),
                 arc_asset_list.clone(),
+                arc_config.clone(),
             )
         })
         .and_then(install_nam)
+        .recover(recover_error)
+        .boxed();
+
+    let post_install_plan = warp::post()
+        .and(warp::path!("install_plan"))
+        .and(warp::body::json())
+        .map(move |json: InstallConfig| {
+            (
+                json,
+                arc_folder_structure_2.clone(),
+                arc_asset_list_2.clone(),
+                arc_config_2.clone(),
+            )
+        })
+        .and_then(install_plan)
+        .recover(recover_error)
+        .boxed();
+
+    let post_cleanup_preview = warp::post()
+        .and(warp::path!("cleanup_preview"))
+        .and(warp::body::json())
+        .map(move |json: InstallConfig| (json, arc_folder_structure_3.clone()))
+        .and_then(cleanup_preview)
+        .recover(recover_error)
+        .boxed();
+
+    let post_upgrade_plan = warp::post()
+        .and(warp::path!("upgrade_plan"))
+        .and(warp::body::json())
+        .map(move |json: InstallConfig| {
+            (
+                json,
+                arc_folder_structure_5.clone(),
+                arc_asset_list_4.clone(),
+                arc_config_4.clone(),
+            )
+        })
+        .and_then(upgrade_plan)
+        .recover(recover_error)
+        .boxed();
+
+    let get_installed = warp::get()
+        .and(warp::path!("installed"))
+        .and(warp::query::<PluginsQuery>())
+        .map(move |query: PluginsQuery| {
+            (
+                query,
+                arc_folder_structure_4.clone(),
+                arc_asset_list_3.clone(),
+                arc_config_3.clone(),
+            )
+        })
+        .and_then(installed_nam)
+        .boxed();
+
+    let get_backups = warp::get()
+        .and(warp::path!("backups"))
+        .and(warp::query::<PluginsQuery>())
+        .and_then(list_backups)
+        .boxed();
+
+    let get_backup_files = warp::get()
+        .and(warp::path!("backups" / "files"))
+        .and(warp::query::<BackupQuery>())
+        .and_then(list_backup_files)
+        .recover(recover_error)
+        .boxed();
+
+    let post_restore_backup = warp::post()
+        .and(warp::path!("backups" / "restore"))
+        .and(warp::body::json())
+        .and_then(restore_backup)
+        .recover(recover_error)
+        .boxed();
+
+    let post_cancel_install = warp::post()
+        .and(warp::path!("install" / "cancel"))
+        .and_then(cancel_install)
+        .boxed();
+
+    let get_journal = warp::get()
+        .and(warp::path!("journal"))
+        .and(warp::query::<PluginsQuery>())
+        .and_then(load_journal)
+        .recover(recover_error)
+        .boxed();
+
+    let post_resume_install = warp::post()
+        .and(warp::path!("journal" / "resume"))
+        .and(warp::body::json())
+        .map(move |json: PluginsQuery| (json, arc_asset_list_7.clone(), arc_config_7.clone()))
+        .and_then(resume_install)
+        .recover(recover_error)
+        .boxed();
+
+    let post_rollback_install = warp::post()
+        .and(warp::path!("journal" / "rollback"))
+        .and(warp::body::json())
+        .and_then(rollback_install)
+        .recover(recover_error)
         .boxed();
 
     let any = warp::any()
@@ -349,6 +542,18 @@ async fn main() -> anyhow::Result<()> {
             .or(post_check_path)
             .or(post_patch_exe)
             .or(post_install_list)
+            .or(post_install_plan)
+            .or(post_cleanup_preview)
+            .or(get_installed)
+            .or(get_previous_selection)
+            .or(post_upgrade_plan)
+            .or(get_backups)
+            .or(get_backup_files)
+            .or(post_restore_backup)
+            .or(post_cancel_install)
+            .or(get_journal)
+            .or(post_resume_install)
+            .or(post_rollback_install)
             .or(any),
     );
     let port: u16 = config.clone().web_server_port;
@@ -364,6 +569,70 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+// backups [--plugins <path>]                         lists the backup sets
+// backups <set id> [--plugins <path>]                lists the files in a set
+// restore <set id> [file ...] [--plugins <path>] [--force]
+async fn run_backup_command(args: &[String]) -> anyhow::Result<()> {
+    let mut location = None;
+    let mut force = false;
+    let mut rest = Vec::new();
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--plugins" => location = iter.next().cloned(),
+            "--force" => force = true,
+            _ => rest.push(arg.clone()),
+        }
+    }
+
+    let plugins = plugins_location(location)
+        .await
+        .map_err(|e| anyhow!("{:?}", e))?;
+
+    match (args[0].as_str(), rest.first()) {
+        ("backups", None) => {
+            for (_, set) in backup::list_backups(&plugins) {
+                println!(
+                    "{}\t{} files\t{} bytes{}",
+                    set.id,
+                    set.files,
+                    set.size,
+                    if set.archive { "\t(zip)" } else { "" }
+                );
+            }
+        }
+        ("backups", Some(id)) => {
+            let set = find_backup_set(&plugins, id)?;
+            for file in backup::read_index(&set)?.files {
+                println!("{}\t{}", file.backup, file.original);
+            }
+        }
+        ("restore", Some(id)) => {
+            let set = find_backup_set(&plugins, id)?;
+            let files = if rest.len() > 1 {
+                Some(&rest[1..])
+            } else {
+                None
+            };
+            let report = backup::restore(&set, files, &installed_file_names(&plugins), force)?;
+            for file in &report.restored {
+                println!("Restored: {}", file);
+            }
+            for conflict in &report.conflicts {
+                println!("Conflict: {}, {}", conflict.file, conflict.reason);
+            }
+            for file in &report.missing {
+                println!("Not in backup set: {}", file);
+            }
+            if !force && !report.conflicts.is_empty() {
+                println!("Conflicting files were not restored, use --force to restore them anyway");
+            }
+        }
+        _ => println!("Usage: restore <set id> [file ...] [--plugins <path>] [--force]"),
+    }
+    Ok(())
+}
+
 async fn select_plugins() -> Result<impl warp::Reply> {
     let def_path = get_def_plugins().await?;
     let selected_path = select_folder_dialog(Some(def_path.as_str())).await?;
@@ -396,6 +665,7 @@ fn check_exe(path: String) -> String {
                 if acceptable_versions.contains(&version.as_str()) {
                     unsafe {
                         CHECKED_EXE = true;
+                        CHECKED_EXE_PATH = Some(path.clone());
                     };
                     serde_json::json!({
                         "version": version.to_string(),
@@ -457,214 +727,1105 @@ fn flatten_installer_options(
     output
 }
 
+// Rules name other options by their path below the installation folder, e.g. `1_Core/RHW`
+fn rule_matches(id: &str, rule: &str) -> bool {
+    id == rule || id.trim_start_matches("top/installation/") == rule.trim_matches('/')
+}
+
+// Rules naming an option that doesn't exist can never be met, or never get in the way
+fn warn_unknown_rules(options: &[InstallerOption]) {
+    let ids = options
+        .iter()
+        .map(|o| format!("{}/{}", o.parent, o.name))
+        .collect::<Vec<String>>();
+    for option in options {
+        for rule in option.requires.iter().chain(&option.conflicts) {
+            if !ids.iter().any(|id| rule_matches(id, rule)) {
+                warn!("Option `{}` names an unknown option in a rule: {}", option.name, rule);
+            }
+        }
+    }
+}
+
+// Checks the `requires` and `conflicts` rules of every selected option, returning each broken rule
+fn check_option_rules(
+    options: &[InstallerOption],
+    selected: &[String],
+) -> std::result::Result<(), Vec<String>> {
+    let is_selected = |rule: &str| selected.iter().any(|s| rule_matches(s, rule));
+    let mut broken = Vec::new();
+
+    for option in options {
+        let id = format!("{}/{}", option.parent, option.name);
+        if !selected.contains(&id) {
+            continue;
+        }
+        for rule in option.requires.iter().filter(|r| !is_selected(r)) {
+            broken.push(format!("`{}` requires `{}`", option.name, rule));
+        }
+        for rule in option.conflicts.iter().filter(|r| is_selected(r)) {
+            broken.push(format!("`{}` conflicts with `{}`", option.name, rule));
+        }
+    }
+
+    if broken.is_empty() {
+        Ok(())
+    } else {
+        Err(broken)
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct InstallConfig {
     files_to_install: Vec<String>,
     location: String,
+    // Paths of the files from the cleanup preview the user agreed to move. Files it matched
+    // that aren't listed are kept, and left out of later previews. Everything matched is
+    // moved when this is left out.
+    #[serde(default)]
+    cleanup: Option<Vec<String>>,
+    // The same for the files matched in the game install's Plugins folder
+    #[serde(default)]
+    game_cleanup: Option<Vec<String>>,
+    // Only change the files that differ from the last install. Without `files_to_install`,
+    // the last install's selection is carried over.
+    #[serde(default)]
+    upgrade: bool,
+    // Build the install next to Plugins first, and leave Plugins as it was if anything fails
+    #[serde(default)]
+    staged: bool,
+    // What to do with files changed since the last install wrote them, by destination, and
+    // for the ones not listed
+    #[serde(default)]
+    modified: std::collections::HashMap<String, ModifiedAction>,
+    #[serde(default)]
+    modified_action: ModifiedAction,
+}
+impl InstallConfig {
+    fn modified_action(&self, destination: &str) -> ModifiedAction {
+        self.modified
+            .get(destination)
+            .copied()
+            .unwrap_or(self.modified_action)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ModifiedAction {
+    // Leave the user's version in place
+    Keep,
+    Overwrite,
+    // Move the user's version to a backup set, then install
+    #[default]
+    Backup,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct FailedFile {
+    destination: String,
+    error: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ModifiedFile {
+    destination: String,
+    action: ModifiedAction,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PlannedFile {
+    // Path of the file in the installation assets
+    source: String,
+    // Where the file is installed, relative to the Plugins folder
+    destination: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PlannedOption {
+    option: String,
+    folder: String,
+    files: Vec<PlannedFile>,
+    ignored: Vec<String>,
+    // Files in Plugins moved because of this option's own cleanup rules
+    cleanup: Vec<cleanup::CleanupMatch>,
+    // Destinations the user changed since the last install wrote them
+    modified: Vec<String>,
+}
+
+// Works out where every file of the selected options is installed, without touching Plugins
+fn plan_install(
+    selected: &[String],
+    options: &[InstallerOption],
+    asset_iter: &InstallAssetList,
+    config: &Configuration,
+) -> Vec<PlannedOption> {
+    let options_by_id: std::collections::HashMap<String, &InstallerOption> = options
+        .iter()
+        .map(|o| (format!("{}/{}", o.parent, o.name), o))
+        .collect();
+
+    let mut plan = Vec::new();
+    for id in selected {
+        let option = match options_by_id.get(id) {
+            Some(opt) if opt.children.is_empty() => opt,
+            _ => continue,
+        };
+        // The nearest placement up the tree applies to the whole branch
+        let mut placement = option.placement.as_ref();
+        let mut parent = options_by_id.get(&option.parent);
+        while let (None, Some(p)) = (placement, parent) {
+            placement = p.placement.as_ref();
+            parent = options_by_id.get(&p.parent);
+        }
+
+        let folder = option.asset_folder();
+        let (files, ignored): (Vec<&String>, Vec<&String>) = asset_iter
+            .folder_files(&folder)
+            .iter()
+            .filter(|f| !is_installer_file(f))
+            .partition(|f| config.is_installable(f));
+
+        plan.push(PlannedOption {
+            option: id.to_owned(),
+            files: files
+                .iter()
+                .map(|file| PlannedFile {
+                    source: file.replace("installation/", ""),
+                    destination: option.destination(file, placement, config),
+                })
+                .collect(),
+            ignored: ignored
+                .iter()
+                .map(|f| prettify_folder_name(f.replace("installation/", "")))
+                .collect(),
+            folder,
+            cleanup: Vec::new(),
+            modified: Vec::new(),
+        });
+    }
+    plan
+}
+
+fn validate_install(
+    install_config: &InstallConfig,
+    options: &[InstallerOption],
+) -> std::result::Result<(), Error> {
+    if !paths::is_folder_named(&install_config.location, "Plugins") {
+        Err(Error::Custom(
+            "Install Location must end in a folder called `Plugins`".to_string(),
+        ))
+    } else if let Err(broken) = check_option_rules(options, &install_config.files_to_install) {
+        Err(Error::InvalidSelection(broken))
+    } else {
+        Ok(())
+    }
+}
+
+async fn install_plan(
+    (install_config, options, asset_iter, config): (
+        InstallConfig,
+        std::sync::Arc<Vec<InstallerOption>>,
+        std::sync::Arc<InstallAssetList>,
+        std::sync::Arc<Configuration>,
+    ),
+) -> Result<impl warp::Reply> {
+    let options = flatten_installer_options(options);
+    validate_install(&install_config, &options)?;
+
+    let mut plan = plan_install(
+        &install_config.files_to_install,
+        &options,
+        &asset_iter,
+        &config,
+    );
+    let (to_move, _) = select_cleanup(&install_config, &options);
+    for file in to_move {
+        let planned = plan
+            .iter_mut()
+            .find(|p| file.option.as_ref() == Some(&p.option));
+        if let Some(planned) = planned {
+            planned.cleanup.push(file);
+        }
+    }
+    let plugins = Path::new(&install_config.location);
+    if let Some(previous) = manifest::InstallManifest::read(plugins) {
+        for planned in plan.iter_mut() {
+            planned.modified = planned
+                .files
+                .iter()
+                .filter(|f| previous.is_modified(plugins, &f.destination))
+                .map(|f| f.destination.clone())
+                .collect();
+        }
+    }
+    Ok(warp::reply::json(&plan))
+}
+
+// The names of the files every option of this package installs
+fn option_files(
+    options: &[InstallerOption],
+    asset_iter: &InstallAssetList,
+    config: &Configuration,
+) -> Vec<(String, Vec<String>)> {
+    let all_options = options
+        .iter()
+        .map(|o| format!("{}/{}", o.parent, o.name))
+        .collect::<Vec<String>>();
+    plan_install(&all_options, options, asset_iter, config)
+        .into_iter()
+        .map(|planned| {
+            let files = planned
+                .files
+                .iter()
+                .filter_map(|f| Path::new(&f.destination).file_name())
+                .map(|f| f.to_string_lossy().to_string())
+                .collect::<Vec<String>>();
+            (planned.option, files)
+        })
+        .collect()
+}
+
+// Reads the manifest an upgrade starts from, and fills in the selection from it if the
+// request has none
+fn prepare_upgrade(
+    install_config: &mut InstallConfig,
+    options: &[InstallerOption],
+    asset_iter: &InstallAssetList,
+    config: &Configuration,
+) -> std::result::Result<(manifest::InstallManifest, upgrade::SelectionChanges), Error> {
+    let previous = manifest::InstallManifest::read(Path::new(&install_config.location))
+        .ok_or_else(|| Error::Custom("No previous install to upgrade".to_string()))?;
+    let selection = upgrade::carry_selection(&previous, &option_files(options, asset_iter, config));
+    if install_config.files_to_install.is_empty() {
+        install_config.files_to_install = selection.selected.clone();
+    }
+    Ok((previous, selection))
+}
+
+fn upgrade_changes(
+    previous: &manifest::InstallManifest,
+    plan: &[PlannedOption],
+    plugins: &Path,
+    asset_iter: &InstallAssetList,
+) -> upgrade::FileChanges {
+    let planned = plan
+        .iter()
+        .flat_map(|p| {
+            p.files.iter().map(move |f| upgrade::UpgradeFile {
+                option: p.option.clone(),
+                source: f.source.clone(),
+                destination: f.destination.clone(),
+            })
+        })
+        .collect();
+    upgrade::diff_files(previous, planned, plugins, |source| {
+        asset_iter
+            .get_file(source)
+            .map(|data| cleanup::sha1_hex(&data))
+    })
+}
+
+async fn upgrade_plan(
+    (mut install_config, options, asset_iter, config): (
+        InstallConfig,
+        std::sync::Arc<Vec<InstallerOption>>,
+        std::sync::Arc<InstallAssetList>,
+        std::sync::Arc<Configuration>,
+    ),
+) -> Result<impl warp::Reply> {
+    let options = flatten_installer_options(options);
+    let (previous, selection) =
+        prepare_upgrade(&mut install_config, &options, &asset_iter, &config)?;
+    validate_install(&install_config, &options)?;
+
+    let plan = plan_install(
+        &install_config.files_to_install,
+        &options,
+        &asset_iter,
+        &config,
+    );
+    let changes = upgrade_changes(
+        &previous,
+        &plan,
+        Path::new(&install_config.location),
+        &asset_iter,
+    );
+    let plugins = Path::new(&install_config.location);
+    let modified = changes
+        .replace
+        .iter()
+        .filter(|f| previous.is_modified(plugins, &f.destination))
+        .map(|f| f.destination.clone())
+        .collect::<Vec<String>>();
+    Ok(warp::reply::json(&serde_json::json!(
+        { "from_version" : previous.nam_version
+        , "to_version" : config.nam_version
+        , "selected" : install_config.files_to_install
+        , "renamed" : selection.renamed
+        , "removed" : selection.removed
+        , "files" : changes
+        , "modified" : modified
+        }
+    )))
+}
+
+// Which NAM is installed in `plugins`, from its manifest or else the files of this release
+fn detect_installed(
+    plugins: &Path,
+    options: &[InstallerOption],
+    asset_iter: &InstallAssetList,
+    config: &Configuration,
+) -> detect::InstalledNam {
+    let fingerprint = release_fingerprint(options, asset_iter, config);
+    let option_files = option_files(options, asset_iter, config);
+    detect::detect(plugins, &fingerprint, &option_files)
+}
+
+// Option ids selected when the installer starts: every checked and locked option whose
+// parents are too, which is one option of each radio group
+fn default_selection(options: &[InstallerOption]) -> Vec<String> {
+    let id = |o: &InstallerOption| format!("{}/{}", o.parent, o.name);
+    let by_id: std::collections::HashMap<String, &InstallerOption> =
+        options.iter().map(|o| (id(o), o)).collect();
+    options
+        .iter()
+        .filter(|o| {
+            let mut current = Some(*o);
+            while let Some(option) = current {
+                if matches!(option.radio_check, RadioCheck::Radio | RadioCheck::Unchecked) {
+                    return false;
+                }
+                current = by_id.get(&option.parent).copied();
+            }
+            true
+        })
+        .map(id)
+        .collect()
+}
+
+// The files of this release's default selection. A real install has only one branch of
+// each radio group, so the files of every option together would rarely be matched.
+fn release_fingerprint(
+    options: &[InstallerOption],
+    asset_iter: &InstallAssetList,
+    config: &Configuration,
+) -> detect::Fingerprint {
+    let files = plan_install(&default_selection(options), options, asset_iter, config)
+        .iter()
+        .flat_map(|planned| planned.files.iter())
+        .filter_map(|f| Path::new(&f.destination).file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .collect();
+    detect::Fingerprint {
+        version: config.nam_version.clone(),
+        files,
+    }
+}
+
+async fn installed_nam(
+    (query, options, asset_iter, config): (
+        PluginsQuery,
+        std::sync::Arc<Vec<InstallerOption>>,
+        std::sync::Arc<InstallAssetList>,
+        std::sync::Arc<Configuration>,
+    ),
+) -> Result<impl warp::Reply> {
+    let plugins = plugins_location(query.location).await?;
+    let options = flatten_installer_options(options);
+    Ok(warp::reply::json(&detect_installed(
+        &plugins,
+        &options,
+        &asset_iter,
+        &config,
+    )))
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PreviousSelection {
+    installed: detect::InstalledNam,
+    // The previous selection, with renamed options under their new ids
+    selected: Vec<String>,
+    // Options of the previous selection this package doesn't have
+    removed: Vec<String>,
+    valid: bool,
+    problems: Vec<String>,
+}
+
+// What was selected in the last install to `plugins`, and whether it's still a valid selection
+fn previous_selection(
+    plugins: &Path,
+    options: &[InstallerOption],
+    asset_iter: &InstallAssetList,
+    config: &Configuration,
+) -> PreviousSelection {
+    let installed = detect_installed(plugins, options, asset_iter, config);
+    let (selected, removed) = match manifest::InstallManifest::read(plugins) {
+        Some(previous) => {
+            let changes =
+                upgrade::carry_selection(&previous, &option_files(options, asset_iter, config));
+            (changes.selected, changes.removed)
+        }
+        None => (installed.options.clone(), Vec::new()),
+    };
+
+    let mut problems = check_option_rules(options, &selected)
+        .err()
+        .unwrap_or_default();
+    let mut radio_parents: Vec<&String> = Vec::new();
+    for option in options {
+        let id = format!("{}/{}", option.parent, option.name);
+        let is_radio = matches!(
+            option.radio_check,
+            RadioCheck::Radio | RadioCheck::RadioChecked
+        );
+        if is_radio && selected.contains(&id) {
+            if radio_parents.contains(&&option.parent) {
+                problems.push(format!(
+                    "More than one option of `{}` is selected",
+                    option.parent
+                ));
+            }
+            radio_parents.push(&option.parent);
+        }
+    }
+
+    PreviousSelection {
+        installed,
+        selected,
+        removed,
+        valid: problems.is_empty(),
+        problems,
+    }
+}
+
+async fn load_previous_selection(
+    (query, options, asset_iter, config): (
+        PluginsQuery,
+        std::sync::Arc<Vec<InstallerOption>>,
+        std::sync::Arc<InstallAssetList>,
+        std::sync::Arc<Configuration>,
+    ),
+) -> Result<impl warp::Reply> {
+    let plugins = plugins_location(query.location).await?;
+    let flat = flatten_installer_options(options);
+    Ok(warp::reply::json(&previous_selection(
+        &plugins,
+        &flat,
+        &asset_iter,
+        &config,
+    )))
+}
+
+// The option tree, with the previous install's selection flagged, and selected to start with
+// if it's still valid
+async fn load_structure(
+    (query, options, asset_iter, config): (
+        PluginsQuery,
+        std::sync::Arc<Vec<InstallerOption>>,
+        std::sync::Arc<InstallAssetList>,
+        std::sync::Arc<Configuration>,
+    ),
+) -> Result<impl warp::Reply> {
+    let plugins = plugins_location(query.location).await?;
+    let previous = previous_selection(
+        &plugins,
+        &flatten_installer_options(options.clone()),
+        &asset_iter,
+        &config,
+    );
+
+    let mut structure = options.to_vec();
+    if previous.installed.installed {
+        for option in structure.iter_mut() {
+            option.mark_previous(&previous.selected, previous.valid);
+        }
+    }
+    Ok(warp::reply::json(&structure))
+}
+
+// The rules of `cleanup.txt` together with those of the selected options
+fn cleanup_rules(selected: &[String], options: &[InstallerOption]) -> cleanup::CleanupRules {
+    let mut rules = cleanup::CleanupRules::parse(CLEANUP);
+    for option in options.iter().filter(|o| !o.cleanup.is_empty()) {
+        let id = format!("{}/{}", option.parent, option.name);
+        if selected.contains(&id) {
+            rules.add_option(&id, &option.cleanup);
+        }
+    }
+    rules
+}
+
+// The cleanup matches to move for this install, and the ones the user declined in the preview
+fn select_cleanup(
+    install_config: &InstallConfig,
+    options: &[InstallerOption],
+) -> (Vec<cleanup::CleanupMatch>, Vec<cleanup::CleanupMatch>) {
+    let rules = cleanup_rules(&install_config.files_to_install, options);
+    let mut to_move = Vec::new();
+    let mut declined = Vec::new();
+    for (plugins, game, accepted) in cleanup_locations(install_config) {
+        let (matches, _) = cleanup::preview(&plugins, &rules);
+        for mut file in matches {
+            file.game = game;
+            match accepted {
+                Some(accepted) if !accepted.contains(&file.path) => declined.push(file),
+                _ => to_move.push(file),
+            }
+        }
+    }
+    (to_move, declined)
+}
+
+// The Plugins folder of the game install, next to the `Apps` folder of the exe `/check_path`
+// validated. SimCity 4 loads plugins from there as well as from the user's Plugins.
+fn game_plugins() -> Option<std::path::PathBuf> {
+    let exe = unsafe { CHECKED_EXE_PATH.clone() }?;
+    let plugins = paths::resolve(&Path::new(&exe).parent()?.parent()?.join("Plugins"));
+    if plugins.is_dir() {
+        Some(plugins)
+    } else {
+        None
+    }
+}
+
+// The Plugins folders to clean, whether each is the game's, and the files accepted there
+fn cleanup_locations(
+    install_config: &InstallConfig,
+) -> Vec<(std::path::PathBuf, bool, Option<&Vec<String>>)> {
+    let user = std::path::PathBuf::from(&install_config.location);
+    let mut locations = vec![(user.clone(), false, install_config.cleanup.as_ref())];
+    match game_plugins() {
+        Some(game) if paths::resolve(&user) != game => {
+            locations.push((game, true, install_config.game_cleanup.as_ref()))
+        }
+        _ => (),
+    }
+    locations
+}
+
+// The files in Plugins cleanup would move, for the user to choose from before installing
+async fn cleanup_preview(
+    (install_config, options): (InstallConfig, std::sync::Arc<Vec<InstallerOption>>),
+) -> Result<impl warp::Reply> {
+    let options = flatten_installer_options(options);
+    let rules = cleanup_rules(&install_config.files_to_install, &options);
+    let mut files = Vec::new();
+    let mut kept = Vec::new();
+    for (plugins, game, _) in cleanup_locations(&install_config) {
+        let (offered, previously_kept) = cleanup::preview(&plugins, &rules);
+        files.extend(
+            offered
+                .into_iter()
+                .map(|f| cleanup::CleanupMatch { game, ..f }),
+        );
+        kept.extend(
+            previously_kept
+                .into_iter()
+                .map(|f| cleanup::CleanupMatch { game, ..f }),
+        );
+    }
+    Ok(warp::reply::json(&serde_json::json!(
+        { "files" : files
+        , "kept" : kept
+        }
+    )))
+}
+
+async fn install_nam(
+    (mut install_config, options, asset_iter, config): (
+        InstallConfig,
+        std::sync::Arc<Vec<InstallerOption>>,
+        std::sync::Arc<InstallAssetList>,
+        std::sync::Arc<Configuration>,
+    ),
+) -> Result<impl warp::Reply> {
+    let options = flatten_installer_options(options);
+
+    // The status and any cancel requested belong to the install already running
+    if INSTALL_RUNNING.load(Ordering::SeqCst) {
+        return Err(Error::Custom("An install is already running".to_string()).into());
+    }
+    let plugins = Path::new(&install_config.location);
+    if journal::exists(plugins) {
+        return Err(Error::Custom(format!(
+            "An unfinished install into {} has to be resumed or rolled back first",
+            install_config.location
+        ))
+        .into());
+    }
+
+    let previous = if install_config.upgrade {
+        let (previous, selection) =
+            prepare_upgrade(&mut install_config, &options, &asset_iter, &config)?;
+        for renamed in &selection.renamed {
+            info!("Option {} is now {}", renamed.from, renamed.to);
+        }
+        for removed in &selection.removed {
+            warn!("Option {} is no longer part of the NAM", removed);
+        }
+        Some(previous)
+    } else {
+        manifest::InstallManifest::read(plugins)
+    };
+
+    if let Err(e) = validate_install(&install_config, &options) {
+        Err(e.into())
+    } else {
+        let plan = plan_install(
+            &install_config.files_to_install,
+            &options,
+            &asset_iter,
+            &config,
+        );
+        claim_install()?;
+        reset_install_status();
+        std::thread::spawn(move || {
+            // An upgrade leaves the files that haven't changed, and removes those no longer
+            // installed along with the cleanup
+            let changes = previous
+                .as_ref()
+                .filter(|_| install_config.upgrade)
+                .map(|previous| {
+                    upgrade_changes(
+                        previous,
+                        &plan,
+                        Path::new(&install_config.location),
+                        &asset_iter,
+                    )
+                });
+
+            let journal = journal_install(
+                &install_config,
+                &options,
+                &plan,
+                previous.as_ref(),
+                changes.as_ref(),
+                &config,
+            )
+            .and_then(|journal| journal.begin());
+            match journal {
+                Ok(journal) => run_journal(journal, &asset_iter),
+                Err(e) => {
+                    warn!("Couldn't start the install: {}", e.to_string());
+                    unsafe {
+                        INSTALL_ERROR = Some(e.to_string());
+                    };
+                    INSTALL_RUNNING.store(false, Ordering::SeqCst);
+                }
+            }
+        });
+
+        Ok(serde_json::json!(
+            { "cleaning_count" : 0.0
+            , "cleaning_max" : 0.0
+            , "installed_count" : 0.0
+            , "installed_max" : 0.0
+            , "files_cleaned" : []
+            , "files_copied" : []
+            , "files_ignored" : []
+            }
+        )
+        .to_string())
+    }
 }
-async fn install_nam(
-    (install_config, options, asset_iter): (
-        InstallConfig,
-        std::sync::Arc<Vec<InstallerOption>>,
-        std::sync::Arc<InstallAssetList>,
-    ),
-) -> Result<impl warp::Reply> {
+
+fn reset_install_status() {
     unsafe {
         CLEANED_FILE_COUNT = 0;
         CLEANED_FILE_MAX = 0;
         CLEANED_FILE_LIST = Vec::new();
+        CLEANED_FILE_MATCHES = Vec::new();
         INSTALLED_FILE_COUNT = 0;
         INSTALLED_FILE_MAX = 0;
         INSTALLED_FILE_LIST = Vec::new();
+        IGNORED_FILE_LIST = Vec::new();
+        MODIFIED_FILE_LIST = Vec::new();
+        FAILED_FILE_LIST = Vec::new();
+        INSTALL_ROLLED_BACK = false;
+        INSTALL_CANCELLED = false;
+        INSTALL_ERROR = None;
     };
+    CANCEL_INSTALL.store(false, Ordering::SeqCst);
+}
 
-    if !&install_config.location.ends_with("Plugins") {
-        Err(
-            Error::Custom("Install Location must end in a folder called `Plugins`".to_string())
-                .into(),
-        )
-    } else {
-        let options = flatten_installer_options(options);
-        std::thread::spawn(move || {
-            // Clean Out old files (Cleanitol)
-            let files_to_move = CLEANUP
-                .lines()
-                .map(|f| f.to_owned())
-                .collect::<Vec<String>>();
-            let plugins_dir = walkdir::WalkDir::new(&install_config.location);
-            std::fs::create_dir(&install_config.location.replace("Plugins", "Plugins_bak"))
-                .unwrap_or_else(|e| warn!("Unable to create plugins_bak dir: {}", e.to_string()));
-            let max_clean = files_to_move.len();
+fn add_failed_file(destination: String, error: String) {
+    unsafe {
+        let mut nl = FAILED_FILE_LIST.clone();
+        nl.push(FailedFile { destination, error });
+        FAILED_FILE_LIST = nl;
+    };
+}
+
+// Works out every step of the install before any is taken, so the journal has them all
+fn journal_install(
+    install_config: &InstallConfig,
+    options: &[InstallerOption],
+    plan: &[PlannedOption],
+    previous: Option<&manifest::InstallManifest>,
+    changes: Option<&upgrade::FileChanges>,
+    config: &Configuration,
+) -> anyhow::Result<journal::Journal> {
+    let plugins = Path::new(&install_config.location);
+    let mut journal = journal::Journal::new(
+        plugins,
+        &config.nam_version,
+        config.backup_retention,
+        manifest::InstallManifest {
+            nam_version: config.nam_version.clone(),
+            installer_version: config.rust_version.clone(),
+            selected: install_config.files_to_install.clone(),
+            files: Vec::new(),
+        },
+    );
+    journal.staged = install_config.staged;
+    // Files moved out of the way before the install writes over them
+    let mut backed_up = std::collections::HashSet::new();
+
+    // Clean Out old files (Cleanitol)
+    let (to_move, declined) = select_cleanup(install_config, options);
+    let removed = changes.map(|c| c.remove.as_slice()).unwrap_or_default();
+    for (location_plugins, game, _) in cleanup_locations(install_config) {
+        let location = location_plugins.to_string_lossy().to_string();
+        let declined = declined
+            .iter()
+            .filter(|m| m.location == location)
+            .map(|m| m.path.clone())
+            .collect::<Vec<_>>();
+        journal.kept.push((location_plugins.clone(), declined));
+
+        let mut moves = Vec::new();
+        for file in to_move.iter().filter(|m| m.location == location) {
+            moves.push((
+                location_plugins.join(&file.path),
+                journal::BackupReason::Cleanup(file.clone()),
+            ));
+        }
+        for file in removed.iter().filter(|_| !game) {
+            let path = paths::resolve(&location_plugins.join(&file.destination));
+            if path.exists() {
+                moves.push((path, journal::BackupReason::Removed(file.destination.clone())));
+            }
+        }
+        if moves.is_empty() {
+            continue;
+        }
+
+        // The game's Plugins may not be writable, which only leaves its files where they are
+        let set = match backup::BackupSet::create(&location_plugins, config.backup_as_zip) {
+            Ok(set) => journal.add_set(set),
+            Err(e) if !install_config.staged => {
+                warn!("Unable to create backup set in {}: {}", location, e.to_string());
+                for (path, _) in moves {
+                    add_failed_file(
+                        path.to_string_lossy().to_string(),
+                        format!("couldn't create a backup set: {}", e),
+                    );
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        for (path, reason) in moves {
+            backed_up.insert(path.clone());
+            journal.steps.push(journal::Step::Backup {
+                set,
+                file: path,
+                reason,
+            });
+        }
+    }
+
+    // A staged install builds every file first, then cleans up and moves them into place
+    let cleanup_steps = match install_config.staged {
+        true => std::mem::take(&mut journal.steps),
+        false => Vec::new(),
+    };
+    let mut placed = Vec::new();
+
+    let mut modified_set = None;
+    for planned in plan {
+        for file in &planned.ignored {
+            warn!("Ignoring file of a type that isn't installed: {}", file);
+            unsafe {
+                let mut nl = IGNORED_FILE_LIST.clone();
+                nl.push(file.to_owned());
+                IGNORED_FILE_LIST = nl;
+            };
+        }
+
+        for file in &planned.files {
+            let path = paths::resolve(&plugins.join(&file.destination));
+            let unchanged = changes
+                .filter(|c| c.is_unchanged(&file.destination))
+                .and(previous)
+                .and_then(|p| p.files.iter().find(|f| f.destination == file.destination));
+            if let Some(unchanged) = unchanged {
+                if path.exists() {
+                    info!("Unchanged since the last install: {}", file.destination);
+                    journal.manifest.files.push(manifest::ManifestFile {
+                        option: planned.option.clone(),
+                        ..unchanged.clone()
+                    });
+                    continue;
+                }
+            }
 
-            for (count, file) in plugins_dir.into_iter().enumerate() {
+            // Files changed by hand since the last install are kept, overwritten or backed
+            // up first, as set by the install's `modified` and `modified_action`
+            let modified = previous.filter(|p| p.is_modified(plugins, &file.destination));
+            if let Some(previous) = modified {
+                let action = install_config.modified_action(&file.destination);
+                warn!("User modified file: {}, {:?}", file.destination, action);
                 unsafe {
-                    CLEANED_FILE_COUNT = count;
-                    CLEANED_FILE_MAX = max_clean;
-                    let mut nl = CLEANED_FILE_LIST.clone();
-                    let f_n = file
-                        .as_ref()
-                        .unwrap()
-                        .file_name()
-                        .to_string_lossy()
-                        .to_string();
-                    nl.push(f_n);
-                    CLEANED_FILE_LIST = nl;
+                    let mut nl = MODIFIED_FILE_LIST.clone();
+                    nl.push(ModifiedFile {
+                        destination: file.destination.clone(),
+                        action,
+                    });
+                    MODIFIED_FILE_LIST = nl;
                 };
-                match &file {
-                    Ok(f) => {
-                        let f_n = f.file_name().to_string_lossy().to_string();
-
-                        if files_to_move.contains(&f_n) {
-                            let possible_dir = &f
-                                .path()
-                                .to_string_lossy()
-                                .to_string()
-                                .replace(
-                                    &format!(
-                                        "/{}",
-                                        &f.path()
-                                            .file_name()
-                                            .unwrap()
-                                            .to_string_lossy()
-                                            .to_string()
-                                    ),
-                                    "",
-                                )
-                                .replace("Plugins", "Plugins_bak");
-
-                            std::fs::create_dir_all(possible_dir).unwrap_or_else(|e| {
-                                warn!(
-                                    "Unable to create dir in plugins_bak because: {}.",
-                                    e.to_string()
-                                )
+                match action {
+                    ModifiedAction::Keep => {
+                        if let Some(kept) = previous.find(&file.destination) {
+                            journal.manifest.files.push(manifest::ManifestFile {
+                                option: planned.option.clone(),
+                                ..kept.clone()
                             });
-
-                            match std::fs::rename(
-                                f.path(),
-                                f.path()
-                                    .to_string_lossy()
-                                    .to_string()
-                                    .replace("Plugins", "Plugins_bak"),
-                            ) {
-                                Ok(_) => info!("Successfully moved file: {} to plugins_bak", &f_n),
-                                Err(e) => warn!(
-                                    "Unable to move file: {}, to plugins_bak: {}",
-                                    &f_n,
-                                    e.to_string()
-                                ),
-                            };
-                        } else {
-                            continue;
                         }
-                    }
-                    Err(e) => {
-                        warn!("{}", e.to_string());
                         continue;
                     }
+                    ModifiedAction::Backup => {
+                        let set = match modified_set.take() {
+                            Some(set) => set,
+                            None => backup::BackupSet::create(plugins, config.backup_as_zip)
+                                .map(|set| journal.add_set(set))
+                                .map_err(|e| e.to_string()),
+                        };
+                        modified_set = Some(set.clone());
+                        // Without a backup the user's file is left alone
+                        let set = match set {
+                            Ok(set) => set,
+                            Err(e) if !install_config.staged => {
+                                add_failed_file(
+                                    file.destination.clone(),
+                                    format!("couldn't create a backup set: {}", e),
+                                );
+                                continue;
+                            }
+                            Err(e) => return Err(anyhow!("couldn't create a backup set: {}", e)),
+                        };
+                        backed_up.insert(path.clone());
+                        placed.push(journal::Step::Backup {
+                            set,
+                            file: path.clone(),
+                            reason: journal::BackupReason::Modified(file.destination.clone()),
+                        });
+                    }
+                    ModifiedAction::Overwrite => (),
                 }
             }
 
-            // Retrieve the files from the binary
-            let mut chosen_options: Vec<InstallerOption> = Vec::new();
-            for file in install_config.files_to_install {
-                let opt: Vec<InstallerOption> = options
-                    .iter()
-                    .filter(|o| format!("{}/{}", o.parent, o.name.clone()) == file)
-                    .map(|o| o.to_owned())
-                    .collect();
-                if opt.len() > 0 {
-                    chosen_options.push(opt[0].clone())
-                } else {
-                    continue;
-                }
+            placed.push(journal.write_step(
+                &planned.option,
+                &file.source,
+                &file.destination,
+                path.exists() && !backed_up.contains(&path),
+            ));
+            if install_config.staged {
+                journal.steps.push(journal::Step::Stage {
+                    source: file.source.clone(),
+                    destination: file.destination.clone(),
+                });
+            } else {
+                journal.steps.append(&mut placed);
             }
-            let files_to_install = chosen_options
-                .iter()
-                .filter(|o| o.children.len() == 0)
-                .map(|o| format!("{}/{}", o.location.clone(), o.original_name.clone()))
-                .collect::<Vec<String>>();
-
-            let file_list: Vec<String> = asset_iter
-                .list
-                .iter()
-                .filter(|f| f.contains(".dat"))
-                .map(|f| f.to_owned())
-                .collect();
-
-            let max_install = files_to_install.len();
-            for (count, file_name) in files_to_install.iter().enumerate() {
-                let file_name = file_name.replace("installation/", "");
-                // println!("File Name: {:#?}", file_name);
-
-                let filtered_file_list: Vec<&String> = file_list
-                    .iter()
-                    .filter(|f| f.contains(&file_name))
-                    .collect();
+        }
+        // In a staged install, an option is only done once its files have been moved into
+        // Plugins, after the cleanup
+        placed.push(journal::Step::OptionDone {
+            folder: prettify_folder_name(planned.folder.to_owned()),
+        });
+        if !install_config.staged {
+            journal.steps.append(&mut placed);
+        }
+    }
+    journal.steps.extend(cleanup_steps);
+    journal.steps.extend(placed);
+    Ok(journal)
+}
 
-                for file in filtered_file_list {
-                    let file_data = asset_iter.list.iter().find(|f| f == &file);
-                    match file_data {
-                        Some(f) => {
-                            info!("Retrieved file: {}", file);
-                            let splits = file.split("/").collect::<Vec<&str>>();
-                            let folder = format!(
-                                "{}/{}",
-                                &install_config.location,
-                                prettify_folder_name(splits[..splits.len() - 1].join("/"))
-                            );
-                            let file_location = format!(
-                                "{}/{}/{}",
-                                install_config.location,
-                                prettify_folder_name(splits[..splits.len() - 1].join("/")),
-                                prettify_folder_name(splits[splits.len() - 1..].concat())
-                            );
-
-                            std::fs::create_dir_all(folder).unwrap_or_else(|e| {
-                                warn!("Couldn't create install directories: {}", e.to_string())
-                            });
+// Takes the steps of the journal not done yet, then finishes the install. Steps done before
+// an interruption still count towards the install status.
+fn run_journal(mut journal: journal::Journal, asset_iter: &InstallAssetList) {
+    let is_cleanup = |step: &journal::Step| match step {
+        journal::Step::Backup { reason, .. } => {
+            !matches!(reason, journal::BackupReason::Modified(_))
+        }
+        _ => false,
+    };
+    let max_clean = journal.steps.iter().filter(|s| is_cleanup(s)).count();
+    let max_install = journal
+        .steps
+        .iter()
+        .filter(|s| matches!(s, journal::Step::OptionDone { .. }))
+        .count();
+    unsafe {
+        CLEANED_FILE_MAX = max_clean;
+        INSTALLED_FILE_MAX = max_install;
+    };
 
-                            match std::fs::write(&file_location, f) {
-                                Ok(_) => {
-                                    info!("Successfully wrote file: {}", &file_location);
-                                }
-                                Err(e) => {
-                                    warn!(
-                                        "Couldn't write file: {} because {}",
-                                        &file_location,
-                                        e.to_string()
-                                    );
-                                    continue;
-                                }
-                            };
+    let mut cleaned = 0;
+    let mut installed = 0;
+    // Modified files that couldn't be backed up, and so aren't overwritten
+    let mut not_backed_up = Vec::new();
+    // A staged install stops at the first step that fails
+    let mut failed = false;
+    let mut cancelled = false;
+    INSTALL_RUNNING.store(true, Ordering::SeqCst);
+    for i in 0..journal.steps.len() {
+        if CANCEL_INSTALL.load(Ordering::SeqCst) {
+            cancelled = true;
+        }
+        if failed || cancelled {
+            break;
+        }
+        let done = journal.is_done(i);
+        match journal.steps[i].clone() {
+            journal::Step::Backup { file, reason, .. } => {
+                if !done {
+                    if let Err(e) = journal.backup(i) {
+                        warn!(
+                            "Unable to move file: {:?}, to plugins_bak: {}",
+                            file,
+                            e.to_string()
+                        );
+                        if let journal::BackupReason::Modified(destination) = &reason {
+                            not_backed_up.push(destination.clone());
+                        }
+                        failed = journal.staged;
+                        continue;
+                    }
+                    info!("Successfully moved file: {:?} to plugins_bak", file);
+                }
+                unsafe {
+                    match reason {
+                        journal::BackupReason::Cleanup(file) => {
+                            let mut nl = CLEANED_FILE_LIST.clone();
+                            nl.push(file.path.clone());
+                            CLEANED_FILE_LIST = nl;
+                            let mut nm = CLEANED_FILE_MATCHES.clone();
+                            nm.push(file);
+                            CLEANED_FILE_MATCHES = nm;
                         }
-                        None => {
-                            warn!("Couldn't retrieve file: {}", file);
-                            continue;
+                        journal::BackupReason::Removed(destination) => {
+                            let mut nl = CLEANED_FILE_LIST.clone();
+                            nl.push(destination);
+                            CLEANED_FILE_LIST = nl;
                         }
+                        journal::BackupReason::Modified(_) => (),
+                    }
+                };
+                if is_cleanup(&journal.steps[i]) {
+                    cleaned += 1;
+                    unsafe {
+                        CLEANED_FILE_COUNT = cleaned;
                     };
                 }
+            }
+            journal::Step::Write {
+                source,
+                destination,
+                ..
+            }
+            | journal::Step::Stage {
+                source,
+                destination,
+            } => {
+                if done {
+                    continue;
+                }
+                let staging = matches!(journal.steps[i], journal::Step::Stage { .. });
+                // Retrieve the files from the binary
+                let written = if not_backed_up.contains(&destination) {
+                    Err(anyhow!("the modified file couldn't be backed up"))
+                } else if journal.staged && !staging {
+                    journal.place(i)
+                } else {
+                    match asset_iter.get_file(&source) {
+                        Some(f) if staging => journal.stage(i, &f),
+                        Some(f) => {
+                            info!("Retrieved file: {}", source);
+                            journal.write(i, &f)
+                        }
+                        None => Err(anyhow!("couldn't retrieve {}", source)),
+                    }
+                };
+                match written {
+                    Ok(sha1) => {
+                        info!("Successfully wrote file: {}", destination);
+                        if let Err(e) = journal.mark_done(i, Some(sha1)) {
+                            warn!("Couldn't update the install journal: {}", e.to_string());
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Couldn't write file: {} because {}", destination, e);
+                        add_failed_file(destination, e.to_string());
+                        failed = journal.staged;
+                    }
+                }
+                continue;
+            }
+            journal::Step::OptionDone { folder } => {
+                installed += 1;
                 unsafe {
-                    let count = count + 1;
-                    INSTALLED_FILE_COUNT = count;
-                    INSTALLED_FILE_MAX = max_install;
+                    INSTALLED_FILE_COUNT = installed;
                     let mut nl = INSTALLED_FILE_LIST.clone();
-                    nl.push(prettify_folder_name(file_name));
+                    nl.push(folder);
                     INSTALLED_FILE_LIST = nl;
                 };
             }
-        });
-
-        Ok(serde_json::json!(
-            { "cleaning_count" : 0.0
-            , "cleaning_max" : 0.0
-            , "installed_count" : 0.0
-            , "installed_max" : 0.0
-            , "files_cleaned" : []
-            , "files_copied" : []
+        }
+        if !done {
+            if let Err(e) = journal.mark_done(i, None) {
+                warn!("Couldn't update the install journal: {}", e.to_string());
             }
-        )
-        .to_string())
+        }
+    }
+
+    if failed || cancelled {
+        if cancelled {
+            warn!("Install cancelled, rolling back");
+        } else {
+            warn!("Staged install failed, rolling back");
+        }
+        match journal.rollback() {
+            Ok(_) => unsafe {
+                INSTALL_ROLLED_BACK = true;
+                INSTALL_CANCELLED = cancelled;
+            },
+            Err(e) => warn!("Couldn't roll back the install: {}", e.to_string()),
+        }
+    } else {
+        match journal.commit() {
+            Ok(_) => info!("Wrote install manifest"),
+            Err(e) => warn!("Couldn't finish the install: {}", e.to_string()),
+        }
     }
+    INSTALL_RUNNING.store(false, Ordering::SeqCst);
+}
+
+// Stops the running install before its next step and rolls it back. An install that is
+// already being finished can't be cancelled.
+async fn cancel_install() -> Result<impl warp::Reply> {
+    let running = INSTALL_RUNNING.load(Ordering::SeqCst);
+    if running {
+        info!("Cancelling the install");
+        CANCEL_INSTALL.store(true, Ordering::SeqCst);
+    }
+    Ok(warp::reply::json(
+        &serde_json::json!({ "cancelling" : running }),
+    ))
 }
 
 async fn patch_exe_windows(path: String) -> Result<impl warp::Reply> {
@@ -733,11 +1894,229 @@ async fn get_def_plugins() -> Result<String> {
     let user_dir = directories::UserDirs::new().unwrap();
     let home_dir = user_dir.home_dir().to_string_lossy().to_string();
 
-    match os_info::get().os_type().to_string().to_lowercase().as_str() {
-        "windows" => Ok(format!("{}\\Documents\\SimCity 4\\Plugins", home_dir)),
-        "macos" => Ok(format!("{}/Documents/SimCity 4/Plugins", home_dir)),
-        _ => Ok(format!("{}/Documents/SimCity 4/Plugins", home_dir)),
+    let plugins = match os_info::get().os_type().to_string().to_lowercase().as_str() {
+        "windows" => format!("{}\\Documents\\SimCity 4\\Plugins", home_dir),
+        "macos" => format!("{}/Documents/SimCity 4/Plugins", home_dir),
+        _ => format!("{}/Documents/SimCity 4/Plugins", home_dir),
+    };
+    Ok(paths::resolve(Path::new(&plugins))
+        .to_string_lossy()
+        .to_string())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PluginsQuery {
+    location: Option<String>,
+}
+
+// The Plugins folder asked for, or else the default one
+async fn plugins_location(location: Option<String>) -> Result<std::path::PathBuf> {
+    match location {
+        Some(l) => Ok(l.into()),
+        None => Ok(get_def_plugins().await?.into()),
+    }
+}
+
+async fn list_backups(query: PluginsQuery) -> Result<impl warp::Reply> {
+    let plugins = plugins_location(query.location).await?;
+    let sets: Vec<backup::BackupSummary> = backup::list_backups(&plugins)
+        .into_iter()
+        .map(|(_, summary)| summary)
+        .collect();
+    Ok(warp::reply::json(&sets))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BackupQuery {
+    location: Option<String>,
+    id: String,
+}
+
+fn find_backup_set(plugins: &Path, id: &str) -> std::result::Result<std::path::PathBuf, Error> {
+    backup::find_set(plugins, id).ok_or_else(|| Error::Custom(format!("No backup set: {}", id)))
+}
+
+async fn list_backup_files(query: BackupQuery) -> Result<impl warp::Reply> {
+    let plugins = plugins_location(query.location).await?;
+    let set = find_backup_set(&plugins, &query.id)?;
+    let index = backup::read_index(&set).map_err(|e| Error::Custom(e.to_string()))?;
+    Ok(warp::reply::json(&index))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RestoreRequest {
+    location: Option<String>,
+    id: String,
+    // Paths inside the set, all files when left out
+    files: Option<Vec<String>>,
+    #[serde(default)]
+    force: bool,
+}
+
+// Files installed into `plugins` by the last install, which restored files mustn't clash with
+fn installed_file_names(plugins: &Path) -> std::collections::HashSet<String> {
+    manifest::InstallManifest::read(plugins)
+        .map(|m| m.file_names())
+        .unwrap_or_default()
+}
+
+async fn restore_backup(request: RestoreRequest) -> Result<impl warp::Reply> {
+    let plugins = plugins_location(request.location).await?;
+    let set = find_backup_set(&plugins, &request.id)?;
+    let report = backup::restore(
+        &set,
+        request.files.as_deref(),
+        &installed_file_names(&plugins),
+        request.force,
+    )
+    .map_err(|e| Error::Custom(e.to_string()))?;
+    Ok(warp::reply::json(&report))
+}
+
+// Marks an install as running, unless one already is
+fn claim_install() -> std::result::Result<(), Error> {
+    INSTALL_RUNNING
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .map(|_| ())
+        .map_err(|_| Error::Custom("An install is already running".to_string()))
+}
+
+fn open_journal(plugins: &Path) -> std::result::Result<journal::Journal, Error> {
+    match journal::Journal::open(plugins) {
+        Some(journal) => {
+            journal.map_err(|e| Error::Custom(format!("Unreadable install journal: {}", e)))
+        }
+        None => Err(Error::Custom(format!(
+            "No unfinished install in {}",
+            plugins.display()
+        ))),
+    }
+}
+
+// The journal of an install that was cut off, so it can be resumed or rolled back
+async fn load_journal(query: PluginsQuery) -> Result<impl warp::Reply> {
+    let plugins = plugins_location(query.location).await?;
+    let summary = match journal::exists(&plugins) {
+        true => Some(open_journal(&plugins)?.summary()),
+        false => None,
+    };
+    Ok(warp::reply::json(&serde_json::json!(
+        { "unfinished" : summary.is_some()
+        , "journal" : summary
+        }
+    )))
+}
+
+async fn resume_install(
+    (query, asset_iter, config): (
+        PluginsQuery,
+        std::sync::Arc<InstallAssetList>,
+        std::sync::Arc<Configuration>,
+    ),
+) -> Result<impl warp::Reply> {
+    let plugins = plugins_location(query.location).await?;
+    claim_install()?;
+    let journal = open_journal(&plugins).and_then(|journal| {
+        if journal.nam_version != config.nam_version {
+            return Err(Error::Custom(format!(
+                "The install was started by NAM {}, and can only be rolled back",
+                journal.nam_version
+            )));
+        }
+        Ok(journal)
+    });
+    let journal = match journal {
+        Ok(journal) => journal,
+        Err(e) => {
+            INSTALL_RUNNING.store(false, Ordering::SeqCst);
+            return Err(e.into());
+        }
+    };
+    reset_install_status();
+    std::thread::spawn(move || run_journal(journal, &asset_iter));
+    Ok(warp::reply::json(&serde_json::json!({ "resumed" : true })))
+}
+
+async fn rollback_install(query: PluginsQuery) -> Result<impl warp::Reply> {
+    let plugins = plugins_location(query.location).await?;
+    // A running install's journal is still being written to
+    claim_install()?;
+    let rolled_back = open_journal(&plugins).and_then(|journal| {
+        journal
+            .rollback()
+            .map_err(|e| Error::Custom(e.to_string()))
+    });
+    INSTALL_RUNNING.store(false, Ordering::SeqCst);
+    rolled_back?;
+    Ok(warp::reply::json(
+        &serde_json::json!({ "rolled_back" : true }),
+    ))
+}
+
+// Asks on the console what to do with an install into the default Plugins folder that was
+// cut off. Installs elsewhere are offered by the front end, through `/journal`.
+async fn offer_unfinished_install(
+    asset_iter: &InstallAssetList,
+    config: &Configuration,
+) -> anyhow::Result<()> {
+    let plugins = plugins_location(None)
+        .await
+        .map_err(|e| anyhow!("{:?}", e))?;
+    let journal = match journal::Journal::open(&plugins) {
+        Some(Ok(journal)) => journal,
+        Some(Err(e)) => {
+            warn!("Unreadable install journal in {:?}: {}", plugins, e.to_string());
+            println!(
+                "An install into {} was cut off, but its journal can't be read: {}",
+                plugins.display(),
+                e
+            );
+            println!("Type d to discard it, or press enter to decide later:");
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            if answer.trim().eq_ignore_ascii_case("d") {
+                match journal::discard(&plugins) {
+                    Ok(_) => println!("Discarded the journal.\n"),
+                    Err(e) => println!("Couldn't discard the journal: {}\n", e),
+                }
+            } else {
+                println!("Left the journal as it is.\n");
+            }
+            return Ok(());
+        }
+        None => return Ok(()),
+    };
+
+    let summary = journal.summary();
+    println!(
+        "An install into {} was cut off after {} of {} steps.",
+        summary.location, summary.done, summary.steps
+    );
+    let can_resume = journal.nam_version == config.nam_version;
+    if can_resume {
+        println!("Type r to resume it, b to roll it back, or press enter to decide later:");
+    } else {
+        println!(
+            "It was started by NAM {}. Type b to roll it back, or press enter to decide later:",
+            journal.nam_version
+        );
+    }
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+
+    match answer.trim().to_lowercase().as_str() {
+        "r" if can_resume => {
+            reset_install_status();
+            run_journal(journal, asset_iter);
+            println!("Finished the install.\n");
+        }
+        "b" => match journal.rollback() {
+            Ok(_) => println!("Rolled back the install.\n"),
+            Err(e) => println!("Couldn't roll back the install: {}\n", e),
+        },
+        _ => println!("Left the install as it is.\n"),
     }
+    Ok(())
 }
 
 async fn find_plugins() -> Result<impl warp::Reply> {
@@ -752,7 +2131,14 @@ async fn load_install_status() -> Result<impl warp::Reply> {
             , "installed_count" : INSTALLED_FILE_COUNT
             , "installed_max" : INSTALLED_FILE_MAX
             , "files_cleaned" : CLEANED_FILE_LIST
+            , "cleaned_matches" : CLEANED_FILE_MATCHES
             , "files_copied" : INSTALLED_FILE_LIST
+            , "files_ignored" : IGNORED_FILE_LIST
+            , "files_modified" : MODIFIED_FILE_LIST
+            , "files_failed" : FAILED_FILE_LIST
+            , "rolled_back" : INSTALL_ROLLED_BACK
+            , "cancelled" : INSTALL_CANCELLED
+            , "error" : INSTALL_ERROR
             }
         )
         .to_string())
@@ -760,11 +2146,7 @@ async fn load_install_status() -> Result<impl warp::Reply> {
 }
 
 async fn load_local_file(
-    (file_name, asset_list, temp_folder_uuid): (
-        String,
-        std::sync::Arc<InstallAssetList>,
-        std::sync::Arc<String>,
-    ),
+    (file_name, asset_list): (String, std::sync::Arc<InstallAssetList>),
 ) -> Result<impl warp::Reply> {
     let name = percent_encoding::percent_decode_str(&file_name)
         .decode_utf8_lossy()
@@ -772,21 +2154,19 @@ async fn load_local_file(
     let folder = name.replace("installation/", "");
 
     if folder == "installation" || folder == "/Network Addon Mod" {
-        Ok(
-            match asset_list.get_file("Main.txt", temp_folder_uuid.clone()) {
-                Some(a) => String::from_utf8_lossy(&a).to_string(),
-                _ => "".to_string(),
-            },
-        )
+        Ok(match asset_list.get_file("Main.txt") {
+            Some(a) => String::from_utf8_lossy(&a).to_string(),
+            _ => "".to_string(),
+        })
     } else {
         let list = asset_list.clone();
         let files: Vec<&String> = list
             .list
             .iter()
-            .filter(|f| f.clone().contains(&folder))
+            .filter(|f| paths::contains_ignore_case(f, &folder))
             .filter(|f| {
-                let s = f.clone();
-                let idx = s.rfind(&folder).unwrap();
+                let s = f.to_lowercase();
+                let idx = s.rfind(&folder.to_lowercase()).unwrap();
                 let count = s[idx..].matches("\\").count();
                 count < 2
             })
@@ -794,10 +2174,13 @@ async fn load_local_file(
             .collect();
 
         let mut texts = Vec::new();
+        if let Some(description) = asset_list.get_metadata(&folder).and_then(|m| m.description) {
+            texts.push(description);
+        }
         for file in files {
             let file = file.replace("installation/", "").replace("\\", "/");
 
-            texts.push(match asset_list.get_file(&file, temp_folder_uuid.clone()) {
+            texts.push(match asset_list.get_file(&file) {
                 Some(a) => String::from_utf8_lossy(&a).to_string(),
                 _ => "".to_string(),
             })
@@ -806,11 +2189,7 @@ async fn load_local_file(
     }
 }
 async fn load_local_image(
-    (file_name, asset_list, temp_folder_uuid): (
-        String,
-        std::sync::Arc<InstallAssetList>,
-        std::sync::Arc<String>,
-    ),
+    (file_name, asset_list): (String, std::sync::Arc<InstallAssetList>),
 ) -> Result<impl warp::Reply> {
     let name = percent_encoding::percent_decode_str(&file_name)
         .decode_utf8_lossy()
@@ -819,7 +2198,7 @@ async fn load_local_image(
 
     if folder == "installation" || folder == "/Network Addon Mod" {
         let resp = asset_list
-            .get_file("Network Addon Mod.png", temp_folder_uuid.clone())
+            .get_file("Network Addon Mod.png")
             .unwrap()
             .to_vec();
         Ok(resp)
@@ -828,10 +2207,10 @@ async fn load_local_image(
         let files: Vec<&String> = list
             .list
             .iter()
-            .filter(|f| f.clone().contains(&folder))
+            .filter(|f| paths::contains_ignore_case(f, &folder))
             .filter(|f| {
-                let s = f.clone();
-                let idx = s.rfind(&folder).unwrap();
+                let s = f.to_lowercase();
+                let idx = s.rfind(&folder.to_lowercase()).unwrap();
                 let count = s[idx..].matches("\\").count();
                 count < 2
             })
@@ -839,15 +2218,19 @@ async fn load_local_image(
             .collect();
 
         let mut images = Vec::new();
+        if let Some(image) = asset_list.get_metadata(&folder).and_then(|m| m.image) {
+            match asset_list.get_file(&format!("{}/{}", folder, image)) {
+                Some(img) => images.push(img.to_vec()),
+                None => warn!("Couldn't retrieve image: {}/{}", folder, image),
+            }
+        }
         for file in files {
             let file = file.replace("installation/", "").replace("\\", "/");
 
             images.push(
-                match asset_list.get_file(&file, temp_folder_uuid.clone()) {
+                match asset_list.get_file(&file) {
                     Some(img) => img,
-                    None => asset_list
-                        .get_file("Network Addon Mod.png", temp_folder_uuid.clone())
-                        .unwrap(),
+                    None => asset_list.get_file("Network Addon Mod.png").unwrap(),
                 }
                 .to_vec(),
             )
@@ -855,7 +2238,7 @@ async fn load_local_image(
         let resp = &match images.get(0) {
             Some(a) => a.to_owned(),
             _ => asset_list
-                .get_file("Network Addon Mod.png", temp_folder_uuid)
+                .get_file("Network Addon Mod.png")
                 .unwrap()
                 .to_vec(),
         };
@@ -907,6 +2290,8 @@ pub enum Error {
     Forbidden,
     #[error("IO: {0}")]
     IO(#[from] std::io::Error),
+    #[error("Invalid selection: {}", .0.join("; "))]
+    InvalidSelection(Vec<String>),
 }
 impl warp::reject::Reject for Error {}
 
@@ -937,6 +2322,16 @@ pub async fn handle_rejection(error: warp::reject::Rejection) -> Result<impl war
     };
     Ok(error_msg)
 }
+
+// Replies with this app's errors, and leaves any other rejection to the remaining routes
+async fn recover_error(error: warp::reject::Rejection) -> Result<impl warp::Reply> {
+    if error.find::<Error>().is_some() {
+        handle_rejection(error).await
+    } else {
+        Err(error)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, PartialEq, Ord, PartialOrd, Eq)]
 enum RadioCheck {
     Radio,
@@ -961,6 +2356,30 @@ impl RadioCheck {
         }
     }
 
+    // Options without a `selection` are checkboxes
+    fn from_metadata(meta: &OptionMetadata) -> Self {
+        match (&meta.selection, meta.default) {
+            (Some(SelectionType::Checkbox) | None, Some(false)) => RadioCheck::Unchecked,
+            (Some(SelectionType::Checkbox) | None, _) => RadioCheck::Checked,
+            (Some(SelectionType::Radio), Some(true)) => RadioCheck::RadioChecked,
+            (Some(SelectionType::Radio), _) => RadioCheck::Radio,
+            (Some(SelectionType::RadioFolder), _) => RadioCheck::RadioFolder,
+            (Some(SelectionType::Locked), _) => RadioCheck::Locked,
+            (Some(SelectionType::ParentLocked), _) => RadioCheck::ParentLocked,
+        }
+    }
+
+    // The same kind of option, selected or not
+    fn with_selected(&self, selected: bool) -> Self {
+        match self {
+            RadioCheck::Checked | RadioCheck::Unchecked if selected => RadioCheck::Checked,
+            RadioCheck::Checked | RadioCheck::Unchecked => RadioCheck::Unchecked,
+            RadioCheck::Radio | RadioCheck::RadioChecked if selected => RadioCheck::RadioChecked,
+            RadioCheck::Radio | RadioCheck::RadioChecked => RadioCheck::Radio,
+            other => other.clone(),
+        }
+    }
+
     fn determine(s: &str) -> Self {
         if s.contains("~") {
             RadioCheck::Locked
@@ -980,6 +2399,28 @@ impl RadioCheck {
     }
 }
 
+// The `$1`..`$9` in a folder name sets where it is listed among its siblings
+fn folder_order(s: &str) -> Option<u16> {
+    let idx = s.find('$')?;
+    s[idx + 1..]
+        .chars()
+        .next()
+        .and_then(|c| c.to_digit(10))
+        .filter(|n| *n > 0)
+        .map(|n| n as u16)
+}
+
+// Orders options by their `order`, those without one last, and then by name
+fn sort_options(options: &mut [InstallerOption]) {
+    options.sort_by(|a, b| {
+        (a.order.unwrap_or(u16::MAX), &a.name, &a.original_name).cmp(&(
+            b.order.unwrap_or(u16::MAX),
+            &b.name,
+            &b.original_name,
+        ))
+    });
+}
+
 fn prettify_folder_name(s: String) -> String {
     s.replace("$1", "")
         .replace("$2", "")
@@ -999,6 +2440,68 @@ fn prettify_folder_name(s: String) -> String {
         .replace("*", "")
 }
 
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum SelectionType {
+    Checkbox,
+    Radio,
+    RadioFolder,
+    Locked,
+    ParentLocked,
+}
+
+// Contents of an `option.json` in an option folder. When present it replaces the symbols
+// in the folder name, and any field left out falls back to a plain checked option.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct OptionMetadata {
+    name: Option<String>,
+    selection: Option<SelectionType>,
+    default: Option<bool>,
+    order: Option<u16>,
+    description: Option<String>,
+    image: Option<String>,
+    target: Option<String>,
+    placement: Option<Placement>,
+    // Placements for single files of the option, by file name
+    files: std::collections::BTreeMap<String, Placement>,
+    requires: Vec<String>,
+    conflicts: Vec<String>,
+    // Lines like those of `cleanup.txt`, only used when the option is selected
+    cleanup: Vec<String>,
+}
+impl OptionMetadata {
+    // The paths below Plugins it names
+    fn paths(&self) -> Vec<&str> {
+        let placements = self
+            .placement
+            .iter()
+            .chain(self.files.values())
+            .filter_map(|p| match p {
+                Placement::Root => None,
+                Placement::Folder(path) | Placement::Path(path) => Some(path.as_str()),
+            });
+        self.target
+            .iter()
+            .map(|t| t.as_str())
+            .chain(self.files.keys().map(|f| f.as_str()))
+            .chain(placements)
+            .collect()
+    }
+}
+
+// Where an option's files go in Plugins when they can't follow the option tree
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Ord, PartialOrd, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Placement {
+    // Directly in the Plugins folder, as DLL plugins need to be
+    Root,
+    // A load order folder directly in Plugins, e.g. `zzz_NAM Last`
+    Folder(String),
+    // A folder anywhere below Plugins, without any of the option tree's folders
+    Path(String),
+}
+
 #[derive(Debug, Clone, Serialize, PartialEq, Ord, PartialOrd, Eq)]
 struct InstallerOption {
     name: String,
@@ -1008,6 +2511,17 @@ struct InstallerOption {
     children: Vec<InstallerOption>,
     depth: u16,
     parent: String,
+    order: Option<u16>,
+    description: Option<String>,
+    image: Option<String>,
+    target: Option<String>,
+    placement: Option<Placement>,
+    file_placements: std::collections::BTreeMap<String, Placement>,
+    requires: Vec<String>,
+    conflicts: Vec<String>,
+    cleanup: Vec<String>,
+    // Selected in the last install to the Plugins folder `/structure` was asked about
+    previously_selected: bool,
 }
 impl InstallerOption {
     fn new(original_name: String, radio_check: RadioCheck) -> anyhow::Result<Self> {
@@ -1029,6 +2543,8 @@ impl InstallerOption {
             .replace("~", "")
             .replace("*", "");
 
+        let order = folder_order(&original_name);
+
         Ok(InstallerOption {
             name,
             original_name,
@@ -1037,8 +2553,95 @@ impl InstallerOption {
             children: Vec::new(),
             depth: 0,
             parent: "".into(),
+            order,
+            description: None,
+            image: None,
+            target: None,
+            placement: None,
+            file_placements: std::collections::BTreeMap::new(),
+            requires: Vec::new(),
+            conflicts: Vec::new(),
+            cleanup: Vec::new(),
+            previously_selected: false,
         })
     }
+    fn from_metadata(original_name: String, meta: OptionMetadata) -> anyhow::Result<Self> {
+        let radio_check = RadioCheck::from_metadata(&meta);
+        let mut option = InstallerOption::new(original_name.clone(), radio_check)?;
+        option.name = meta.name.unwrap_or(original_name);
+        option.order = meta.order.or(option.order);
+        option.description = meta.description;
+        option.image = meta.image;
+        option.target = meta.target;
+        option.placement = meta.placement;
+        option.file_placements = meta.files;
+        option.requires = meta.requires;
+        option.conflicts = meta.conflicts;
+        option.cleanup = meta.cleanup;
+        Ok(option)
+    }
+    // The option's folder relative to `installation/`
+    fn asset_folder(&self) -> String {
+        let location = self
+            .location
+            .trim_start_matches("installation/installation")
+            .trim_start_matches('/');
+        if location.is_empty() {
+            self.original_name.clone()
+        } else {
+            format!("{}/{}", location, self.original_name)
+        }
+    }
+    // Where a file of this option is installed, relative to the Plugins folder. `file` is the
+    // asset path, and `placement` the nearest one set on the option or above it.
+    fn destination(
+        &self,
+        file: &str,
+        placement: Option<&Placement>,
+        config: &Configuration,
+    ) -> String {
+        let name = Path::new(file)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let placement = match self.file_placements.get(&name) {
+            Some(p) => Some(p),
+            None if config.is_root_file(&name) => Some(&Placement::Root),
+            None => placement,
+        };
+
+        match (placement, &self.target) {
+            (Some(Placement::Root), _) => name,
+            (Some(Placement::Folder(folder)), _) | (Some(Placement::Path(folder)), _) => {
+                format!("{}/{}", folder.trim_matches('/'), name)
+            }
+            (None, Some(target)) => format!("{}/{}", target, name),
+            (None, None) => {
+                let splits = file.split("/").collect::<Vec<&str>>();
+                format!(
+                    "{}/{}",
+                    prettify_folder_name(splits[..splits.len() - 1].join("/")),
+                    prettify_folder_name(splits[splits.len() - 1..].concat())
+                )
+            }
+        }
+    }
+    // Flags the options of the previous selection, and with `apply` also starts them out
+    // selected, and the rest not. Returns whether the option or anything below it was selected.
+    fn mark_previous(&mut self, selected: &[String], apply: bool) -> bool {
+        let mut any_child = false;
+        for child in self.children.iter_mut() {
+            any_child |= child.mark_previous(selected, apply);
+        }
+        let id = format!("{}/{}", self.parent, self.name);
+        self.previously_selected = selected.contains(&id);
+        let selected = self.previously_selected || any_child;
+        if apply {
+            self.radio_check = self.radio_check.with_selected(selected);
+        }
+        selected
+    }
     fn push_children(&self, children: &mut Vec<InstallerOption>) -> Self {
         children.append(self.children.clone().as_mut());
         InstallerOption {
@@ -1049,57 +2652,187 @@ impl InstallerOption {
             children: children.to_vec(),
             depth: self.depth.clone(),
             parent: self.parent.clone(),
+            order: self.order,
+            description: self.description.clone(),
+            image: self.image.clone(),
+            target: self.target.clone(),
+            placement: self.placement.clone(),
+            file_placements: self.file_placements.clone(),
+            requires: self.requires.clone(),
+            conflicts: self.conflicts.clone(),
+            cleanup: self.cleanup.clone(),
+            previously_selected: self.previously_selected,
         }
     }
 }
 
-fn folder_structure(dir: &str) -> anyhow::Result<InstallerOption> {
+// Builds the option tree from the asset paths, relative to `installation/`. Every folder
+// is an option, and the tree keeps the `installation` node the front end expects.
+fn folder_structure(
+    paths: &[String],
+    metadata: &std::collections::HashMap<String, OptionMetadata>,
+) -> anyhow::Result<InstallerOption> {
+    let mut folders = std::collections::BTreeSet::new();
+    for path in paths {
+        let splits = path.split('/').collect::<Vec<&str>>();
+        for end in 1..splits.len() {
+            folders.insert(splits[..end].join("/"));
+        }
+    }
+
     let options =
         InstallerOption::new("Network Addon Mod".to_string(), RadioCheck::new("Locked")?)?;
-
-    Ok(options.push_children(parse_folder(dir, 0, "top", "installation")?.as_mut()))
+    let mut installation = InstallerOption::new(
+        "installation".to_string(),
+        RadioCheck::determine("installation"),
+    )?;
+    installation.depth = 1;
+    installation.parent = "top".into();
+    installation.location = "installation".into();
+
+    let mut children = parse_folder(
+        &folders,
+        metadata,
+        "",
+        1,
+        "top/installation",
+        "installation/installation",
+        None,
+    )?;
+    let tree = options.push_children(&mut vec![installation.push_children(&mut children)]);
+    warn_unknown_rules(&flatten_installer_options(std::sync::Arc::new(vec![tree.clone()])));
+    Ok(tree)
 }
 
 fn parse_folder(
+    folders: &std::collections::BTreeSet<String>,
+    metadata: &std::collections::HashMap<String, OptionMetadata>,
     dir: &str,
     parent_depth: u16,
     parent_name: &str,
     original_parent_name: &str,
+    parent_target: Option<&str>,
 ) -> anyhow::Result<Vec<InstallerOption>> {
-    let files = std::fs::read_dir(dir)?;
     let mut options = Vec::new();
 
-    for entry in files.into_iter() {
-        match entry {
-            Ok(e) => {
-                // println!("{:#?}", &e);
-                let f_n = &e.file_name().to_str().unwrap().to_owned();
-
-                let local_res = if e.metadata()?.is_dir() {
-                    let mut local_option =
-                        InstallerOption::new(f_n.to_string(), RadioCheck::determine(&f_n))?;
-                    local_option.depth = parent_depth + 1;
-                    local_option.parent = parent_name.into();
-                    local_option.location = original_parent_name.into();
-                    let mut children = parse_folder(
-                        &e.path().to_str().unwrap(),
-                        parent_depth + 1,
-                        &format!("{}/{}", parent_name, &local_option.name),
-                        &format!("{}/{}", original_parent_name, &local_option.original_name),
-                    )?;
-                    local_option.push_children(children.as_mut())
-                } else {
-                    // InstallerOption::new(f_n, RadioCheck::determine(&f_n))?
-                    continue;
-                };
-                options.push(local_res);
-            }
-            Err(e) => {
-                warn!("Error reading file: {}", e);
-                continue;
-            }
+    let sub_folders = folders.iter().filter(|f| match f.rfind('/') {
+        Some(idx) => f[..idx] == *dir,
+        None => dir.is_empty(),
+    });
+    for folder in sub_folders {
+        let f_n = folder.rsplit('/').next().unwrap().to_owned();
+
+        let (mut local_option, path_name) = match metadata.get(folder) {
+            Some(meta) => (
+                InstallerOption::from_metadata(f_n.clone(), meta.clone())?,
+                Some(f_n.clone()),
+            ),
+            None => (
+                InstallerOption::new(f_n.clone(), RadioCheck::determine(&f_n))?,
+                None,
+            ),
+        };
+        // Options without their own target install below their parent's one. Folders with
+        // metadata keep their name as is, as it has no symbols to strip.
+        if local_option.target.is_none() {
+            local_option.target = match (parent_target, path_name) {
+                (Some(t), name) => Some(format!(
+                    "{}/{}",
+                    t,
+                    name.unwrap_or_else(|| prettify_folder_name(f_n.clone()))
+                )),
+                // Like the asset path the other options install to, `installation/` included
+                (None, Some(name)) => Some(format!(
+                    "{}/{}",
+                    prettify_folder_name(format!("installation/{}", dir))
+                        .trim_end_matches('/'),
+                    name
+                )),
+                (None, None) => None,
+            };
         }
+        local_option.depth = parent_depth + 1;
+        local_option.parent = parent_name.into();
+        local_option.location = original_parent_name.into();
+        let mut children = parse_folder(
+            folders,
+            metadata,
+            folder,
+            parent_depth + 1,
+            &format!("{}/{}", parent_name, &local_option.name),
+            &format!("{}/{}", original_parent_name, &local_option.original_name),
+            local_option.target.as_deref(),
+        )?;
+        options.push(local_option.push_children(children.as_mut()));
     }
-    // println!("{:#?}", &options);
+    sort_options(&mut options);
     Ok(options)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn paths(list: &[&str]) -> Vec<String> {
+        list.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn tree_keeps_the_installation_node() {
+        let tree = folder_structure(&paths(&["Foo/f.dat"]), &HashMap::new()).unwrap();
+        assert_eq!(tree.name, "Network Addon Mod");
+        assert_eq!(tree.radio_check, RadioCheck::Locked);
+        assert_eq!(tree.children.len(), 1);
+
+        let installation = &tree.children[0];
+        assert_eq!(installation.name, "installation");
+        assert_eq!(installation.parent, "top");
+        assert_eq!(installation.depth, 1);
+        assert_eq!(installation.children[0].name, "Foo");
+        assert_eq!(installation.children[0].parent, "top/installation");
+        assert_eq!(installation.children[0].location, "installation/installation");
+    }
+
+    #[test]
+    fn tree_orders_options_by_their_number() {
+        let tree = folder_structure(
+            &paths(&["Alpha/a.dat", "$2_Second/b.dat", "$1_First/c.dat"]),
+            &HashMap::new(),
+        )
+        .unwrap();
+        let names = tree.children[0]
+            .children
+            .iter()
+            .map(|o| o.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, ["_First", "_Second", "Alpha"]);
+    }
+
+    #[test]
+    fn tree_reads_option_metadata() {
+        let meta: OptionMetadata = serde_json::from_str(
+            r#"{ "name": "Roads and Rails", "selection": "radio", "order": 1 }"#,
+        )
+        .unwrap();
+        let metadata = HashMap::from([("Z#/Roads + Rails".to_string(), meta)]);
+        let tree = folder_structure(
+            &paths(&["Z#/+Other/o.dat", "Z#/Roads + Rails/r.dat"]),
+            &metadata,
+        )
+        .unwrap();
+
+        let group = &tree.children[0].children[0];
+        let option = &group.children[0];
+        assert_eq!(option.name, "Roads and Rails");
+        assert_eq!(option.original_name, "Roads + Rails");
+        assert_eq!(option.radio_check, RadioCheck::Radio);
+        assert_eq!(option.parent, "top/installation/Z");
+        assert_eq!(
+            option.target.as_deref(),
+            Some("installation/Z/Roads + Rails")
+        );
+        assert_eq!(group.children[1].name, "Other");
+        assert_eq!(group.children[1].target, None);
+    }
+}
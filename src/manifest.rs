@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::cleanup;
+use crate::paths;
+
+// Written to the Plugins folder after every install, recording what was installed where
+pub const MANIFEST_FILE: &str = "NAM Installer Manifest.json";
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct InstallManifest {
+    pub nam_version: String,
+    pub installer_version: String,
+    // Option ids as sent by the front end, e.g. `top/installation/1_Core`
+    pub selected: Vec<String>,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ManifestFile {
+    pub option: String,
+    // Path of the file in the installation assets
+    pub source: String,
+    // Where the file was written, relative to the Plugins folder
+    pub destination: String,
+    // SHA-1 of what was written, missing from manifests of older installers
+    #[serde(default)]
+    pub sha1: Option<String>,
+}
+
+impl InstallManifest {
+    pub fn write(&self, plugins: &Path) -> anyhow::Result<()> {
+        paths::write_atomic(
+            &plugins.join(MANIFEST_FILE),
+            serde_json::to_string_pretty(self)?.as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    // The manifest of the last install into `plugins`, if there was one
+    pub fn read(plugins: &Path) -> Option<Self> {
+        let data = std::fs::read(plugins.join(MANIFEST_FILE)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    pub fn find(&self, destination: &str) -> Option<&ManifestFile> {
+        self.files
+            .iter()
+            .find(|f| paths::eq_ignore_case(&f.destination, destination))
+    }
+
+    // Whether the file the install wrote to `destination` has been changed since. Files
+    // recorded without a hash, or no longer there, aren't.
+    pub fn is_modified(&self, plugins: &Path, destination: &str) -> bool {
+        let sha1 = match self.find(destination).and_then(|f| f.sha1.as_ref()) {
+            Some(sha1) => sha1,
+            None => return false,
+        };
+        let path = paths::resolve(&plugins.join(destination));
+        match cleanup::file_sha1(&path) {
+            Ok(hash) => &hash != sha1,
+            Err(_) => false,
+        }
+    }
+
+    // Lowercase names of every file the install wrote
+    pub fn file_names(&self) -> std::collections::HashSet<String> {
+        self.files
+            .iter()
+            .filter_map(|f| Path::new(&f.destination).file_name())
+            .map(|f| f.to_string_lossy().to_lowercase())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_ignores_case() {
+        let manifest = InstallManifest {
+            files: vec![ManifestFile {
+                option: "top/installation/1_Core".to_string(),
+                source: "installation/1_Core/NetworkAddonMod.dat".to_string(),
+                destination: "zzz_NAM/NetworkAddonMod.dat".to_string(),
+                sha1: None,
+            }],
+            ..Default::default()
+        };
+        let found = manifest.find("ZZZ_NAM/NETWORKADDONMOD.DAT").unwrap();
+        assert_eq!(found.destination, "zzz_NAM/NetworkAddonMod.dat");
+        assert!(manifest.find("zzz_NAM/Other.dat").is_none());
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::{Component, Path, PathBuf};
+
+// Windows treats paths case insensitively, and so does SimCity 4. These helpers make Linux,
+// Mac and Wine installs behave the same, so a `plugins` folder or a `NETWORKADDONMOD.dat`
+// is found just like `Plugins` and `NetworkAddonMod.dat`.
+
+pub fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a == b || a.to_lowercase() == b.to_lowercase()
+}
+
+pub fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+// Whether the last folder of `path` is called `name`, e.g. `.../Documents/SimCity 4/plugins/`
+pub fn is_folder_named(path: &str, name: &str) -> bool {
+    match Path::new(path).file_name() {
+        Some(f) => eq_ignore_case(&f.to_string_lossy(), name),
+        None => false,
+    }
+}
+
+// Finds the path as it is cased on disk, matching each part case insensitively when the exact
+// casing doesn't exist. Parts past the first that can't be found are kept as given.
+pub fn resolve(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    let mut exists = true;
+
+    for component in path.components() {
+        let part = match component {
+            Component::Normal(part) if exists => part,
+            other => {
+                resolved.push(other.as_os_str());
+                continue;
+            }
+        };
+        let exact = resolved.join(part);
+        if exact.exists() {
+            resolved = exact;
+            continue;
+        }
+
+        let dir = if resolved.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            resolved.as_path()
+        };
+        let found = std::fs::read_dir(dir).ok().and_then(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .find(|e| eq_ignore_case(&e.file_name().to_string_lossy(), &part.to_string_lossy()))
+        });
+        match found {
+            Some(entry) => resolved.push(entry.file_name()),
+            None => {
+                exists = false;
+                resolved.push(part);
+            }
+        }
+    }
+    resolved
+}
+
+// Whether `path` stays below the folder it is joined to: relative, and without `..`. Both
+// separators and drive letters are checked for, as on Windows.
+pub fn is_contained(path: &str) -> bool {
+    let path = path.replace('\\', "/");
+    !path.contains(':')
+        && Path::new(&path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+// The temporary file next to `path` that `write_atomic` writes to first
+pub fn temp_sibling(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.nam-install.tmp", name))
+}
+
+// Writes to a temporary file next to `path` and renames it into place, so a crash or full disk
+// never leaves a truncated file under the real name for the game to load
+pub fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let temp = temp_sibling(path);
+    let written = std::fs::File::create(&temp).and_then(|mut file| {
+        std::io::Write::write_all(&mut file, data)?;
+        file.sync_all()
+    });
+    match written.and_then(|_| std::fs::rename(&temp, path)) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh folder in the system temp folder, with `files` created below it
+    fn temp_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nam-paths-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn eq_ignore_case_compares_without_case() {
+        assert!(eq_ignore_case("NetworkAddonMod.dat", "NETWORKADDONMOD.dat"));
+        assert!(eq_ignore_case("Plugins", "Plugins"));
+        assert!(!eq_ignore_case("Plugins", "Plugins_bak"));
+    }
+
+    #[test]
+    fn is_folder_named_matches_the_last_folder() {
+        assert!(is_folder_named("/home/user/Documents/SimCity 4/plugins", "Plugins"));
+        assert!(is_folder_named("/home/user/Documents/SimCity 4/PLUGINS/", "Plugins"));
+        assert!(!is_folder_named("/home/user/Documents/SimCity 4/Plugins_bak", "Plugins"));
+        assert!(!is_folder_named("/home/user/Plugins/zzz_NAM", "Plugins"));
+    }
+
+    #[test]
+    fn is_contained_rejects_paths_leaving_the_folder() {
+        assert!(is_contained("Network Addon Mod/Roundabouts"));
+        assert!(is_contained("./zzz_NAM Last"));
+        assert!(!is_contained("../Plugins_bak"));
+        assert!(!is_contained("NAM/../../Documents"));
+        assert!(!is_contained("NAM\\..\\..\\Documents"));
+        assert!(!is_contained("/etc/passwd"));
+        assert!(!is_contained("C:/Windows"));
+        assert!(!is_contained("\\\\server\\share"));
+    }
+
+    #[test]
+    fn resolve_finds_differently_cased_paths() {
+        let dir = temp_dir("resolve", &["plugins/NETWORKADDONMOD.dat"]);
+        assert_eq!(
+            resolve(&dir.join("Plugins/NetworkAddonMod.dat")),
+            dir.join("plugins/NETWORKADDONMOD.dat")
+        );
+        // The exact casing wins when it exists
+        assert_eq!(
+            resolve(&dir.join("plugins/NETWORKADDONMOD.dat")),
+            dir.join("plugins/NETWORKADDONMOD.dat")
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_keeps_missing_parts_as_given() {
+        let dir = temp_dir("resolve-missing", &["plugins/NETWORKADDONMOD.dat"]);
+        assert_eq!(
+            resolve(&dir.join("Plugins/zzz_NAM/New.dat")),
+            dir.join("plugins/zzz_NAM/New.dat")
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::manifest::{InstallManifest, ManifestFile};
+
+// An upgrade only touches the files that differ between the last install, as recorded in its
+// manifest, and what the new selection installs from this package.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpgradeFile {
+    pub option: String,
+    // Path of the file in the installation assets
+    pub source: String,
+    // Where the file goes, relative to the Plugins folder
+    pub destination: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FileChanges {
+    pub add: Vec<UpgradeFile>,
+    pub replace: Vec<UpgradeFile>,
+    // Installed last time, but not part of the new install
+    pub remove: Vec<ManifestFile>,
+    pub unchanged: Vec<UpgradeFile>,
+}
+impl FileChanges {
+    pub fn is_unchanged(&self, destination: &str) -> bool {
+        self.unchanged.iter().any(|f| f.destination == destination)
+    }
+}
+
+fn file_name(path: &str) -> Option<String> {
+    Path::new(path)
+        .file_name()
+        .map(|f| f.to_string_lossy().to_lowercase())
+}
+
+// Sorts the files of the new install by what has to happen to them. A file is unchanged when
+// the last install wrote one with the same hash to the same place, and it's still there as
+// it was written.
+// `package_sha1` hashes a file of the new package by its source path.
+pub fn diff_files(
+    previous: &InstallManifest,
+    planned: Vec<UpgradeFile>,
+    plugins: &Path,
+    package_sha1: impl Fn(&str) -> Option<String>,
+) -> FileChanges {
+    let installed: HashMap<String, &ManifestFile> = previous
+        .files
+        .iter()
+        .map(|f| (f.destination.to_lowercase(), f))
+        .collect();
+    let planned_destinations: HashSet<String> = planned
+        .iter()
+        .map(|f| f.destination.to_lowercase())
+        .collect();
+
+    let mut changes = FileChanges::default();
+    for file in planned {
+        match installed.get(&file.destination.to_lowercase()) {
+            None => changes.add.push(file),
+            Some(old) => {
+                let same = old.sha1.is_some()
+                    && old.sha1 == package_sha1(&file.source)
+                    && plugins.join(&old.destination).exists()
+                    && !previous.is_modified(plugins, &old.destination);
+                if same {
+                    changes.unchanged.push(file);
+                } else {
+                    changes.replace.push(file);
+                }
+            }
+        }
+    }
+    changes.remove = previous
+        .files
+        .iter()
+        .filter(|f| !planned_destinations.contains(&f.destination.to_lowercase()))
+        .cloned()
+        .collect();
+    changes
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RenamedOption {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SelectionChanges {
+    pub selected: Vec<String>,
+    pub renamed: Vec<RenamedOption>,
+    // Selected last time, with nothing like it in this package
+    pub removed: Vec<String>,
+}
+
+// Carries the last install's selection over to this package. An option that no longer exists
+// is taken to be renamed to the option that installs at least half of the files it installed,
+// by name, and is otherwise reported as removed. `option_files` are the file names each
+// option of this package installs.
+pub fn carry_selection(
+    previous: &InstallManifest,
+    option_files: &[(String, Vec<String>)],
+) -> SelectionChanges {
+    let mut changes = SelectionChanges::default();
+    for id in &previous.selected {
+        if option_files.iter().any(|(option, _)| option == id) {
+            if !changes.selected.contains(id) {
+                changes.selected.push(id.to_owned());
+            }
+            continue;
+        }
+
+        let old_files: HashSet<String> = previous
+            .files
+            .iter()
+            .filter(|f| &f.option == id)
+            .filter_map(|f| file_name(&f.destination))
+            .collect();
+        let best = option_files
+            .iter()
+            .map(|(option, files)| {
+                let shared = files
+                    .iter()
+                    .filter(|f| old_files.contains(&f.to_lowercase()))
+                    .count();
+                (option, shared)
+            })
+            .max_by_key(|(_, shared)| *shared);
+
+        match best {
+            Some((to, shared)) if shared > 0 && shared * 2 >= old_files.len() => {
+                changes.renamed.push(RenamedOption {
+                    from: id.to_owned(),
+                    to: to.to_owned(),
+                });
+                if !changes.selected.contains(to) {
+                    changes.selected.push(to.to_owned());
+                }
+            }
+            _ => changes.removed.push(id.to_owned()),
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cleanup::sha1_hex;
+
+    fn installed(option: &str, destination: &str, data: &str) -> ManifestFile {
+        ManifestFile {
+            option: option.to_owned(),
+            source: format!("{}/{}", option, destination),
+            destination: destination.to_owned(),
+            sha1: Some(sha1_hex(data.as_bytes())),
+        }
+    }
+
+    fn planned(option: &str, destination: &str) -> UpgradeFile {
+        UpgradeFile {
+            option: option.to_owned(),
+            source: format!("{}/{}", option, destination),
+            destination: destination.to_owned(),
+        }
+    }
+
+    fn destinations(files: &[UpgradeFile]) -> Vec<&str> {
+        files.iter().map(|f| f.destination.as_str()).collect()
+    }
+
+    #[test]
+    fn diff_sorts_files_by_what_changed() {
+        let plugins = std::env::temp_dir().join(format!("nam-upgrade-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&plugins);
+        std::fs::create_dir_all(&plugins).unwrap();
+        for (file, data) in [("same.dat", "same"), ("old.dat", "old"), ("gone.dat", "gone")] {
+            std::fs::write(plugins.join(file), data).unwrap();
+        }
+        // Changed by the user since it was installed
+        std::fs::write(plugins.join("edited.dat"), "the user's edit").unwrap();
+
+        let previous = InstallManifest {
+            files: vec![
+                installed("Core", "same.dat", "same"),
+                installed("Core", "old.dat", "old"),
+                installed("Core", "edited.dat", "edited"),
+                installed("Core", "gone.dat", "gone"),
+                installed("Core", "deleted.dat", "deleted"),
+            ],
+            ..Default::default()
+        };
+        let package = HashMap::from([
+            ("Core/same.dat", "same"),
+            ("Core/old.dat", "new"),
+            ("Core/edited.dat", "edited"),
+            ("Core/deleted.dat", "deleted"),
+            ("Core/added.dat", "added"),
+        ]);
+        let changes = diff_files(
+            &previous,
+            vec![
+                planned("Core", "same.dat"),
+                planned("Core", "OLD.dat"),
+                planned("Core", "edited.dat"),
+                planned("Core", "deleted.dat"),
+                planned("Core", "added.dat"),
+            ],
+            &plugins,
+            |source| package.get(source).map(|d| sha1_hex(d.as_bytes())),
+        );
+
+        assert_eq!(destinations(&changes.unchanged), ["same.dat"]);
+        assert_eq!(destinations(&changes.add), ["added.dat"]);
+        assert_eq!(
+            destinations(&changes.replace),
+            ["OLD.dat", "edited.dat", "deleted.dat"]
+        );
+        let removed = changes
+            .remove
+            .iter()
+            .map(|f| f.destination.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(removed, ["gone.dat"]);
+        assert!(changes.is_unchanged("same.dat"));
+        std::fs::remove_dir_all(plugins).unwrap();
+    }
+
+    #[test]
+    fn selection_follows_renamed_options() {
+        let previous = InstallManifest {
+            selected: vec![
+                "top/installation/Core".to_owned(),
+                "top/installation/RHW".to_owned(),
+                "top/installation/Old Puzzle Pieces".to_owned(),
+            ],
+            files: vec![
+                installed("top/installation/Core", "Core.dat", ""),
+                installed("top/installation/RHW", "zzz_NAM/RHW_1.dat", ""),
+                installed("top/installation/RHW", "zzz_NAM/RHW_2.dat", ""),
+                installed("top/installation/Old Puzzle Pieces", "Puzzle.dat", ""),
+            ],
+            ..Default::default()
+        };
+        let option_files = vec![
+            ("top/installation/Core".to_owned(), vec!["Core.dat".to_owned()]),
+            (
+                "top/installation/Road Network/RHW".to_owned(),
+                vec!["rhw_1.dat".to_owned(), "RHW_3.dat".to_owned()],
+            ),
+            ("top/installation/Other".to_owned(), vec!["Other.dat".to_owned()]),
+        ];
+
+        let changes = carry_selection(&previous, &option_files);
+        assert_eq!(
+            changes.selected,
+            ["top/installation/Core", "top/installation/Road Network/RHW"]
+        );
+        assert_eq!(changes.renamed.len(), 1);
+        assert_eq!(changes.renamed[0].from, "top/installation/RHW");
+        assert_eq!(changes.renamed[0].to, "top/installation/Road Network/RHW");
+        assert_eq!(changes.removed, ["top/installation/Old Puzzle Pieces"]);
+    }
+}